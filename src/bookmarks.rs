@@ -0,0 +1,444 @@
+use gtk4 as gtk;
+
+use gtk::glib;
+use serde_derive::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use crate::settings;
+
+const BOOKMARKS_FILE_NAME: &'static str = "bookmarks.toml";
+// stands for "/" in names of imported folders, as "/" separates folders
+const FOLDER_SLASH: char = '\u{2215}';
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub uri: String,
+    #[serde(default)]
+    pub title: String,
+    /// path of folders separated by "/", empty for the top level
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub folder: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// unix time the bookmark was added
+    #[serde(default)]
+    pub added: i64,
+}
+
+impl Bookmark {
+    /// Whether any of title, URI, folder or tags contains `text`.
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        text.is_empty()
+            || self.title.to_lowercase().contains(&text)
+            || self.uri.to_lowercase().contains(&text)
+            || self.folder.to_lowercase().contains(&text)
+            || self
+                .tags
+                .iter()
+                .any(|tag| tag.to_lowercase().contains(&text))
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BookmarksFile {
+    #[serde(default)]
+    bookmarks: Vec<Bookmark>,
+}
+
+/// Bookmarks kept in the config directory, shared by all profiles.
+pub struct Bookmarks {
+    bookmarks: RefCell<Vec<Bookmark>>,
+}
+
+impl Bookmarks {
+    pub fn load() -> Self {
+        let bookmarks = match settings::load_config_file(BOOKMARKS_FILE_NAME) {
+            Some(data) => match toml::from_str::<BookmarksFile>(&data) {
+                Ok(file) => file.bookmarks,
+                Err(e) => {
+                    dbg!(e);
+                    Vec::new()
+                }
+            },
+            None => Vec::new(),
+        };
+        Self {
+            bookmarks: RefCell::new(bookmarks),
+        }
+    }
+
+    fn save(&self) {
+        let file = BookmarksFile {
+            bookmarks: self.bookmarks.borrow().clone(),
+        };
+        match toml::to_string(&file) {
+            Ok(data) => settings::save_config_file(BOOKMARKS_FILE_NAME, &data),
+            Err(e) => {
+                dbg!(e);
+            }
+        }
+    }
+
+    /// All bookmarks, sorted by folder and title.
+    pub fn all(&self) -> Vec<Bookmark> {
+        let mut bookmarks = self.bookmarks.borrow().clone();
+        bookmarks.sort_by(|a, b| {
+            (&a.folder, a.title.to_lowercase()).cmp(&(&b.folder, b.title.to_lowercase()))
+        });
+        bookmarks
+    }
+
+    pub fn find(&self, uri: &str) -> Option<Bookmark> {
+        self.bookmarks
+            .borrow()
+            .iter()
+            .find(|bookmark| bookmark.uri == uri)
+            .cloned()
+    }
+
+    /// Add `bookmark`, or replace the one with the same URI.
+    pub fn put(&self, mut bookmark: Bookmark) {
+        {
+            let mut bookmarks = self.bookmarks.borrow_mut();
+            match bookmarks.iter_mut().find(|b| b.uri == bookmark.uri) {
+                Some(b) => {
+                    bookmark.added = b.added;
+                    *b = bookmark;
+                }
+                None => {
+                    if bookmark.added == 0 {
+                        bookmark.added = glib::real_time() / 1_000_000;
+                    }
+                    bookmarks.push(bookmark);
+                }
+            }
+        }
+        self.save();
+    }
+
+    pub fn remove(&self, uri: &str) {
+        self.bookmarks.borrow_mut().retain(|b| b.uri != uri);
+        self.save();
+    }
+
+    /// Add bookmarks from a Netscape bookmark file, skipping URIs already
+    /// bookmarked. Returns the numbers of bookmarks found and added.
+    pub fn import_html(&self, html: &str) -> (usize, usize) {
+        let found = parse_netscape(html);
+        let mut count = 0;
+        {
+            let mut bookmarks = self.bookmarks.borrow_mut();
+            for bookmark in &found {
+                if !bookmarks.iter().any(|b| b.uri == bookmark.uri) {
+                    bookmarks.push(bookmark.clone());
+                    count += 1;
+                }
+            }
+        }
+        if count > 0 {
+            self.save();
+        }
+        (found.len(), count)
+    }
+
+    /// All bookmarks in the Netscape bookmark file format.
+    pub fn export_html(&self) -> String {
+        to_netscape(self.all())
+    }
+}
+
+fn to_netscape(bookmarks: Vec<Bookmark>) -> String {
+    let mut root = Folder::default();
+    for bookmark in bookmarks {
+        let mut folder = &mut root;
+        for name in bookmark.folder.split('/').filter(|name| !name.is_empty()) {
+            folder = folder.folders.entry(name.to_owned()).or_default();
+        }
+        folder.bookmarks.push(bookmark);
+    }
+    let mut html = String::from(
+        "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+         <!-- This is an automatically generated file.\n     \
+         It will be read and overwritten.\n     \
+         DO NOT EDIT! -->\n\
+         <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+         <TITLE>Bookmarks</TITLE>\n\
+         <H1>Bookmarks</H1>\n",
+    );
+    root.write(&mut html, 0);
+    html
+}
+
+#[derive(Default)]
+struct Folder {
+    folders: BTreeMap<String, Folder>,
+    bookmarks: Vec<Bookmark>,
+}
+
+impl Folder {
+    fn write(&self, html: &mut String, depth: usize) {
+        let indent = "    ".repeat(depth);
+        html.push_str(&format!("{}<DL><p>\n", indent));
+        for (name, folder) in &self.folders {
+            html.push_str(&format!(
+                "{}    <DT><H3>{}</H3>\n",
+                indent,
+                glib::markup_escape_text(&name.replace(FOLDER_SLASH, "/"))
+            ));
+            folder.write(html, depth + 1);
+        }
+        for bookmark in &self.bookmarks {
+            let mut attributes = format!("HREF=\"{}\"", glib::markup_escape_text(&bookmark.uri));
+            if bookmark.added > 0 {
+                attributes.push_str(&format!(" ADD_DATE=\"{}\"", bookmark.added));
+            }
+            if !bookmark.tags.is_empty() {
+                attributes.push_str(&format!(
+                    " TAGS=\"{}\"",
+                    glib::markup_escape_text(&bookmark.tags.join(","))
+                ));
+            }
+            html.push_str(&format!(
+                "{}    <DT><A {}>{}</A>\n",
+                indent,
+                attributes,
+                glib::markup_escape_text(&bookmark.title)
+            ));
+        }
+        html.push_str(&format!("{}</DL><p>\n", indent));
+    }
+}
+
+/// Read bookmarks out of a Netscape bookmark file.
+///
+/// The format is loose HTML, so only the tags that matter are looked at:
+/// `<H3>` names the folder of the following `<DL>`, and `<A>` is a bookmark.
+fn parse_netscape(html: &str) -> Vec<Bookmark> {
+    let mut bookmarks = Vec::new();
+    // names of folders entered, None for lists without a heading
+    let mut folders: Vec<Option<String>> = Vec::new();
+    let mut heading: Option<String> = None;
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &rest[..end];
+        rest = &rest[end + 1..];
+        let name = tag
+            .split(|c: char| c.is_ascii_whitespace())
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "h3" => {
+                let (text, after) = element_text(rest, "</h3");
+                heading = Some(unescape(text.trim()).replace('/', &FOLDER_SLASH.to_string()));
+                rest = after;
+            }
+            "dl" => folders.push(heading.take()),
+            "/dl" => {
+                folders.pop();
+            }
+            "a" => {
+                let (text, after) = element_text(rest, "</a");
+                rest = after;
+                let uri = match attribute(tag, "href") {
+                    Some(uri) => uri,
+                    None => continue,
+                };
+                // skip bookmarklets and smart bookmarks
+                if uri.starts_with("javascript:") || uri.starts_with("place:") {
+                    continue;
+                }
+                let tags = attribute(tag, "tags")
+                    .map(|tags| {
+                        tags.split(',')
+                            .map(|tag| tag.trim().to_owned())
+                            .filter(|tag| !tag.is_empty())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let folder: Vec<&str> = folders.iter().flatten().map(|s| s.as_str()).collect();
+                bookmarks.push(Bookmark {
+                    uri,
+                    title: unescape(text.trim()),
+                    folder: folder.join("/"),
+                    tags,
+                    added: attribute(tag, "add_date")
+                        .and_then(|date| date.parse().ok())
+                        .unwrap_or_default(),
+                });
+            }
+            _ => (),
+        }
+    }
+    bookmarks
+}
+
+// split `html` at the closing tag `end` (lowercase), returning the text before
+// it and the rest after it
+fn element_text<'a>(html: &'a str, end: &str) -> (&'a str, &'a str) {
+    match html.to_ascii_lowercase().find(end) {
+        Some(i) => {
+            let after = &html[i..];
+            let after = after.find('>').map_or("", |j| &after[j + 1..]);
+            (&html[..i], after)
+        }
+        None => (html, ""),
+    }
+}
+
+// value of the attribute `name` (lowercase), which may follow any whitespace
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let pattern = format!("{}=\"", name);
+    let start = lower
+        .match_indices(&pattern)
+        .map(|(i, _)| i)
+        .find(|i| *i > 0 && lower.as_bytes()[i - 1].is_ascii_whitespace())?
+        + pattern.len();
+    let end = tag[start..].find('"')? + start;
+    Some(unescape(&tag[start..end]))
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bookmark(uri: &str, title: &str, folder: &str, tags: &[&str], added: i64) -> Bookmark {
+        Bookmark {
+            uri: uri.to_owned(),
+            title: title.to_owned(),
+            folder: folder.to_owned(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            added,
+        }
+    }
+
+    fn summary(bookmarks: &[Bookmark]) -> Vec<(&str, &str, &str)> {
+        bookmarks
+            .iter()
+            .map(|b| (b.uri.as_str(), b.title.as_str(), b.folder.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn nested_folders() {
+        let html = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<DL><p>
+    <DT><A HREF="https://top.example/">Top</A>
+    <DT><H3 ADD_DATE="1">Work</H3>
+    <DL><p>
+        <DT><H3>Docs</H3>
+        <DL><p>
+            <DT><A HREF="https://docs.example/">Docs</A>
+        </DL><p>
+        <DT><A HREF="https://work.example/">Work</A>
+    </DL><p>
+    <DL><p>
+        <DT><A HREF="https://untitled.example/">Untitled folder</A>
+    </DL><p>
+    <DT><A HREF="javascript:alert(1)">Bookmarklet</A>
+</DL><p>
+"#;
+        assert_eq!(
+            summary(&parse_netscape(html)),
+            [
+                ("https://top.example/", "Top", ""),
+                ("https://docs.example/", "Docs", "Work/Docs"),
+                ("https://work.example/", "Work", "Work"),
+                ("https://untitled.example/", "Untitled folder", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn tags_and_dates() {
+        let html = "<DL><p><DT><A HREF=\"https://example.com/\"\n\tADD_DATE=\"1700000000\"\tTAGS=\"rust, gtk,,\">Example</A></DL>";
+        let bookmarks = parse_netscape(html);
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].uri, "https://example.com/");
+        assert_eq!(bookmarks[0].added, 1700000000);
+        assert_eq!(bookmarks[0].tags, ["rust", "gtk"]);
+    }
+
+    #[test]
+    fn attributes() {
+        assert_eq!(attribute("a\nhref=\"x\"", "href").as_deref(), Some("x"));
+        // not the end of another attribute's name
+        assert_eq!(attribute("a data-href=\"x\"", "href"), None);
+        assert_eq!(
+            attribute("a data-href=\"x\" HREF=\"y\"", "href").as_deref(),
+            Some("y")
+        );
+    }
+
+    #[test]
+    fn entities() {
+        let html = "<DT><H3>R&amp;D</H3><DL><DT><A HREF=\"https://example.com/?a=1&amp;b=2\">&lt;b&gt; &quot;quoted&quot; &#39;single&#39; &amp;lt;</A></DL>";
+        let bookmarks = parse_netscape(html);
+        assert_eq!(
+            summary(&bookmarks),
+            [(
+                "https://example.com/?a=1&b=2",
+                "<b> \"quoted\" 'single' &lt;",
+                "R&D"
+            )]
+        );
+    }
+
+    #[test]
+    fn slash_in_folder_name() {
+        let html = "<DT><H3>News/Blogs</H3><DL><DT><A HREF=\"https://blog.example/\">Blog</A></DL>";
+        let bookmarks = parse_netscape(html);
+        assert_eq!(bookmarks[0].folder, "News\u{2215}Blogs");
+        // back to "/" in one folder
+        let exported = to_netscape(bookmarks);
+        assert!(exported.contains("<H3>News/Blogs</H3>"));
+        assert_eq!(parse_netscape(&exported)[0].folder, "News\u{2215}Blogs");
+    }
+
+    #[test]
+    fn round_trip() {
+        let bookmarks = vec![
+            bookmark("https://a.example/", "A & \"B\"", "", &[], 0),
+            bookmark(
+                "https://b.example/?x=<1>",
+                "B",
+                "One/Two",
+                &["t1", "t2"],
+                1700000000,
+            ),
+            bookmark("https://c.example/", "C", "One", &[], 1),
+        ];
+        let imported = parse_netscape(&to_netscape(bookmarks.clone()));
+        let key = |b: &Bookmark| {
+            (
+                b.uri.clone(),
+                b.title.clone(),
+                b.folder.clone(),
+                b.tags.clone(),
+                b.added,
+            )
+        };
+        let mut expected: Vec<_> = bookmarks.iter().map(key).collect();
+        let mut actual: Vec<_> = imported.iter().map(key).collect();
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+    }
+}
@@ -0,0 +1,469 @@
+use gtk4 as gtk;
+
+use gtk::gio::{Cancellable, File};
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use gtk::{
+    Align, Button, Entry, Grid, Label, ListBox, MenuButton, Orientation, Popover, ScrolledWindow,
+    SearchEntry,
+};
+use std::cell::RefCell;
+use std::rc::Rc;
+use webkit6::prelude::*;
+use webkit6::WebView;
+
+use crate::bookmarks::Bookmark;
+use crate::state::AppState;
+
+/// Show whether the page of `webview` is bookmarked on `star_button`.
+pub fn update_star(star_button: &MenuButton, state: &AppState, webview: &WebView) {
+    let bookmarked = webview
+        .uri()
+        .map_or(false, |uri| state.bookmarks.find(&uri).is_some());
+    if bookmarked {
+        star_button.set_icon_name("starred-symbolic");
+        star_button.set_tooltip_text(Some("edit bookmark"));
+    } else {
+        star_button.set_icon_name("non-starred-symbolic");
+        star_button.set_tooltip_text(Some("bookmark this page"));
+    }
+}
+
+/// Popover of `star_button` to add, edit or remove the bookmark of the page.
+pub fn build_star_popover(star_button: &MenuButton, state: Rc<AppState>, webview: &WebView) {
+    let popover = Popover::new();
+    let grid = Grid::builder()
+        .row_spacing(6)
+        .column_spacing(6)
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .build();
+    popover.set_child(Some(&grid));
+
+    let title_entry = Entry::builder().width_chars(32).build();
+    let folder_entry = Entry::builder()
+        .placeholder_text("folder/subfolder")
+        .build();
+    let tags_entry = Entry::builder().placeholder_text("comma separated").build();
+    for (row, (label, entry)) in [
+        ("Title", &title_entry),
+        ("Folder", &folder_entry),
+        ("Tags", &tags_entry),
+    ]
+    .iter()
+    .enumerate()
+    {
+        let label = Label::builder().label(*label).xalign(1.0).build();
+        grid.attach(&label, 0, row as i32, 1, 1);
+        grid.attach(*entry, 1, row as i32, 1, 1);
+    }
+
+    let buttons = gtk::Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .halign(Align::End)
+        .build();
+    let remove_button = Button::with_label("Remove");
+    remove_button.add_css_class("destructive-action");
+    buttons.append(&remove_button);
+    let save_button = Button::with_label("Save");
+    save_button.add_css_class("suggested-action");
+    buttons.append(&save_button);
+    grid.attach(&buttons, 0, 3, 2, 1);
+
+    popover.connect_show(glib::clone!(
+        #[strong]
+        state,
+        #[weak]
+        webview,
+        #[weak]
+        title_entry,
+        #[weak]
+        folder_entry,
+        #[weak]
+        tags_entry,
+        #[weak]
+        remove_button,
+        move |_popover| {
+            let uri = webview.uri().unwrap_or_default();
+            let bookmark = state.bookmarks.find(&uri);
+            remove_button.set_visible(bookmark.is_some());
+            let bookmark = bookmark.unwrap_or_else(|| Bookmark {
+                title: webview.title().unwrap_or_default().to_string(),
+                ..Default::default()
+            });
+            title_entry.set_text(&bookmark.title);
+            folder_entry.set_text(&bookmark.folder);
+            tags_entry.set_text(&bookmark.tags.join(", "));
+            title_entry.grab_focus();
+        }
+    ));
+
+    let save = glib::clone!(
+        #[strong]
+        state,
+        #[weak]
+        webview,
+        #[weak]
+        star_button,
+        #[weak]
+        popover,
+        #[weak]
+        title_entry,
+        #[weak]
+        folder_entry,
+        #[weak]
+        tags_entry,
+        move || {
+            if let Some(uri) = webview.uri() {
+                state.bookmarks.put(Bookmark {
+                    uri: uri.to_string(),
+                    title: title_entry.text().to_string(),
+                    folder: folder_entry
+                        .text()
+                        .split('/')
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .collect::<Vec<_>>()
+                        .join("/"),
+                    tags: tags_entry
+                        .text()
+                        .split(',')
+                        .map(|tag| tag.trim().to_owned())
+                        .filter(|tag| !tag.is_empty())
+                        .collect(),
+                    added: 0,
+                });
+            }
+            update_star(&star_button, &state, &webview);
+            popover.popdown();
+        }
+    );
+    let save = Rc::new(save);
+    save_button.connect_clicked(glib::clone!(
+        #[strong]
+        save,
+        move |_button| save()
+    ));
+    for entry in [&title_entry, &folder_entry, &tags_entry] {
+        entry.connect_activate(glib::clone!(
+            #[strong]
+            save,
+            move |_entry| save()
+        ));
+    }
+    remove_button.connect_clicked(glib::clone!(
+        #[strong]
+        state,
+        #[weak]
+        webview,
+        #[weak]
+        star_button,
+        #[weak]
+        popover,
+        move |_button| {
+            if let Some(uri) = webview.uri() {
+                state.bookmarks.remove(&uri);
+            }
+            update_star(&star_button, &state, &webview);
+            popover.popdown();
+        }
+    ));
+
+    star_button.set_popover(Some(&popover));
+}
+
+/// Popover listing bookmarks, calling `open` with the URI and whether to open
+/// it in a new window.
+pub fn build_bookmarks_popover(state: Rc<AppState>, open: Rc<dyn Fn(&str, bool)>) -> Popover {
+    let popover = Popover::new();
+    let vbox = gtk::Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(6)
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .build();
+    popover.set_child(Some(&vbox));
+
+    let search_entry = SearchEntry::builder()
+        .placeholder_text("Search bookmarks")
+        .build();
+    vbox.append(&search_entry);
+
+    let list_box = ListBox::new();
+    list_box.set_activate_on_single_click(true);
+    let scrolled = ScrolledWindow::builder()
+        .child(&list_box)
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .propagate_natural_height(true)
+        .max_content_height(480)
+        .min_content_width(400)
+        .build();
+    vbox.append(&scrolled);
+
+    let footer = gtk::Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .build();
+    // outcome of the last import or export
+    let message = Label::builder()
+        .hexpand(true)
+        .xalign(0.0)
+        .ellipsize(gtk::pango::EllipsizeMode::End)
+        .css_classes(["dim-label"])
+        .build();
+    footer.append(&message);
+    let import_button = Button::with_label("Import ...");
+    footer.append(&import_button);
+    let export_button = Button::with_label("Export ...");
+    footer.append(&export_button);
+    vbox.append(&footer);
+
+    let uris: Rc<RefCell<Vec<String>>> = Rc::new(RefCell::new(Vec::new()));
+    let populate = Rc::new(glib::clone!(
+        #[strong]
+        state,
+        #[strong]
+        open,
+        #[strong]
+        uris,
+        #[weak]
+        list_box,
+        #[weak]
+        search_entry,
+        #[weak]
+        popover,
+        move || {
+            list_box.remove_all();
+            let mut uris = uris.borrow_mut();
+            uris.clear();
+            let text = search_entry.text();
+            for bookmark in state.bookmarks.all() {
+                if !bookmark.matches(&text) {
+                    continue;
+                }
+                let row = build_row(&bookmark);
+                let new_window_button = Button::builder()
+                    .icon_name("window-new-symbolic")
+                    .tooltip_text("open in new window")
+                    .has_frame(false)
+                    .valign(Align::Center)
+                    .build();
+                new_window_button.connect_clicked(glib::clone!(
+                    #[strong]
+                    open,
+                    #[weak]
+                    popover,
+                    #[strong(rename_to = uri)]
+                    bookmark.uri,
+                    move |_button| {
+                        popover.popdown();
+                        open(&uri, true);
+                    }
+                ));
+                row.append(&new_window_button);
+                list_box.append(&row);
+                uris.push(bookmark.uri);
+            }
+            if uris.is_empty() {
+                let label = Label::builder()
+                    .label("No bookmarks")
+                    .css_classes(["dim-label"])
+                    .margin_top(6)
+                    .margin_bottom(6)
+                    .build();
+                list_box.append(&label);
+            }
+        }
+    ));
+
+    popover.connect_show(glib::clone!(
+        #[strong]
+        populate,
+        #[weak]
+        search_entry,
+        move |_popover| {
+            search_entry.set_text("");
+            populate();
+            search_entry.grab_focus();
+        }
+    ));
+    search_entry.connect_search_changed(glib::clone!(
+        #[strong]
+        populate,
+        move |_entry| populate()
+    ));
+    list_box.connect_row_activated(glib::clone!(
+        #[strong]
+        uris,
+        #[weak]
+        popover,
+        move |_list_box, row| {
+            let uri = uris.borrow().get(row.index() as usize).cloned();
+            if let Some(uri) = uri {
+                popover.popdown();
+                open(&uri, false);
+            }
+        }
+    ));
+
+    popover.connect_closed(glib::clone!(
+        #[weak]
+        message,
+        move |_popover| message.set_label("")
+    ));
+    import_button.connect_clicked(glib::clone!(
+        #[strong]
+        state,
+        #[weak]
+        popover,
+        #[weak]
+        message,
+        move |_button| {
+            let window = popover.root().and_downcast::<gtk::Window>();
+            popover.popdown();
+            let dialog = gtk::FileDialog::builder()
+                .title("Import Bookmarks")
+                .default_filter(&html_filter())
+                .build();
+            glib::MainContext::default().spawn_local(glib::clone!(
+                #[strong]
+                state,
+                #[weak]
+                popover,
+                #[weak]
+                message,
+                async move {
+                    let file = match dialog.open_future(window.as_ref()).await {
+                        Ok(file) => file,
+                        Err(_) => return,
+                    };
+                    let text = match file.load_contents(Cancellable::NONE) {
+                        Ok((data, _)) => {
+                            match state.bookmarks.import_html(&String::from_utf8_lossy(&data)) {
+                                (0, _) => "No bookmarks found".to_owned(),
+                                (found, added) if found == added => {
+                                    format!("Imported {} bookmarks", added)
+                                }
+                                (found, added) => format!(
+                                    "Imported {} bookmarks, {} already bookmarked",
+                                    added,
+                                    found - added
+                                ),
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("failed to import bookmarks: {}", e);
+                            format!("Import failed: {}", e.message())
+                        }
+                    };
+                    message.set_label(&text);
+                    popover.popup();
+                }
+            ));
+        }
+    ));
+    export_button.connect_clicked(glib::clone!(
+        #[strong]
+        state,
+        #[weak]
+        popover,
+        #[weak]
+        message,
+        move |_button| {
+            let window = popover.root().and_downcast::<gtk::Window>();
+            popover.popdown();
+            let dialog = gtk::FileDialog::builder()
+                .title("Export Bookmarks")
+                .initial_name("bookmarks.html")
+                .default_filter(&html_filter())
+                .build();
+            if let Some(folder) = glib::user_special_dir(glib::UserDirectory::Documents) {
+                dialog.set_initial_folder(Some(&File::for_path(&folder)));
+            }
+            glib::MainContext::default().spawn_local(glib::clone!(
+                #[strong]
+                state,
+                #[weak]
+                popover,
+                #[weak]
+                message,
+                async move {
+                    let file = match dialog.save_future(window.as_ref()).await {
+                        Ok(file) => file,
+                        Err(_) => return,
+                    };
+                    let text = match file.replace_contents(
+                        state.bookmarks.export_html().as_bytes(),
+                        None,
+                        false,
+                        gio::FileCreateFlags::NONE,
+                        Cancellable::NONE,
+                    ) {
+                        Ok(_) => format!("Exported {} bookmarks", state.bookmarks.all().len()),
+                        Err(e) => {
+                            eprintln!("failed to export bookmarks: {}", e);
+                            format!("Export failed: {}", e.message())
+                        }
+                    };
+                    message.set_label(&text);
+                    popover.popup();
+                }
+            ));
+        }
+    ));
+
+    popover
+}
+
+fn html_filter() -> gtk::FileFilter {
+    let filter = gtk::FileFilter::new();
+    filter.set_name(Some("HTML bookmark files"));
+    filter.add_mime_type("text/html");
+    filter.add_suffix("html");
+    filter.add_suffix("htm");
+    filter
+}
+
+fn build_row(bookmark: &Bookmark) -> gtk::Box {
+    let hbox = gtk::Box::new(Orientation::Horizontal, 6);
+    let vbox = gtk::Box::builder()
+        .orientation(Orientation::Vertical)
+        .hexpand(true)
+        .build();
+    let title = if bookmark.title.is_empty() {
+        &bookmark.uri
+    } else {
+        &bookmark.title
+    };
+    let title_label = Label::builder()
+        .label(title)
+        .ellipsize(gtk::pango::EllipsizeMode::End)
+        .max_width_chars(48)
+        .xalign(0.0)
+        .tooltip_text(&bookmark.uri)
+        .build();
+    vbox.append(&title_label);
+
+    let mut details = Vec::new();
+    if !bookmark.folder.is_empty() {
+        details.push(bookmark.folder.clone());
+    }
+    details.extend(bookmark.tags.iter().map(|tag| format!("#{}", tag)));
+    if !details.is_empty() {
+        let details_label = Label::builder()
+            .label(details.join(" "))
+            .ellipsize(gtk::pango::EllipsizeMode::End)
+            .max_width_chars(48)
+            .xalign(0.0)
+            .css_classes(["dim-label"])
+            .build();
+        vbox.append(&details_label);
+    }
+    hbox.append(&vbox);
+    hbox
+}
@@ -0,0 +1,72 @@
+use gtk4 as gtk;
+
+use gtk::prelude::*;
+use gtk::{gdk, gio};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use webkit6::prelude::*;
+
+use crate::restore::WindowState;
+use crate::viewer::Viewer;
+
+const MAX_CLOSED_WINDOWS: usize = 16;
+
+pub struct ClosedWindow {
+    pub state: WindowState,
+    pub favicon: Option<gdk::Texture>,
+}
+
+/// Recently closed windows, the most recent first.
+pub struct ClosedWindows {
+    windows: RefCell<VecDeque<ClosedWindow>>,
+    // "app.reopen-closed" items of `windows`, shared by all windows
+    menu: gio::Menu,
+}
+
+impl ClosedWindows {
+    pub fn new() -> Self {
+        Self {
+            windows: RefCell::new(VecDeque::new()),
+            menu: gio::Menu::new(),
+        }
+    }
+
+    pub fn menu(&self) -> gio::Menu {
+        self.menu.clone()
+    }
+
+    pub fn push(&self, win: &gtk::Window) {
+        if let Some(state) = WindowState::of(win) {
+            let favicon = win
+                .child()
+                .and_downcast::<Viewer>()
+                .and_then(|viewer| viewer.webview().favicon());
+            let mut windows = self.windows.borrow_mut();
+            windows.push_front(ClosedWindow { state, favicon });
+            windows.truncate(MAX_CLOSED_WINDOWS);
+        }
+        self.update_menu();
+    }
+
+    pub fn take(&self, index: usize) -> Option<ClosedWindow> {
+        let closed = self.windows.borrow_mut().remove(index);
+        self.update_menu();
+        closed
+    }
+
+    fn update_menu(&self) {
+        self.menu.remove_all();
+        for (i, closed) in self.windows.borrow().iter().enumerate() {
+            let label = closed.state.title.as_deref().unwrap_or(&closed.state.uri);
+            let item = gio::MenuItem::new(Some(label), None);
+            item.set_action_and_target_value(
+                Some("app.reopen-closed"),
+                Some(&(i as u32).to_variant()),
+            );
+            if let Some(favicon) = &closed.favicon {
+                item.set_icon(favicon);
+            }
+            self.menu.append_item(&item);
+        }
+    }
+}
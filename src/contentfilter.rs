@@ -0,0 +1,512 @@
+use gtk4 as gtk;
+
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::Application;
+use serde_derive::Serialize;
+use std::cell::RefCell;
+use std::rc::Rc;
+use webkit6::prelude::*;
+use webkit6::{UserContentFilter, UserContentFilterStore, WebView};
+
+use crate::rules;
+use crate::settings::{self, Settings};
+use crate::viewer::Viewer;
+
+// filter lists are read from $XDG_CONFIG_HOME/wv/filters/
+const FILTERS_DIR_NAME: &'static str = "filters";
+// compiled filters are cached in $XDG_CACHE_HOME/wv/content-filters/
+const CACHE_DIR_NAME: &'static str = "content-filters";
+const CHECKSUM_FILE_NAME: &'static str = "checksum";
+const FILTER_ID: &'static str = "wv-filters";
+// part of the checksum; change it when convert() changes what it makes
+const CONVERTER_VERSION: &'static str = "1";
+
+/// Ad and tracker blocking with filter lists compiled to a WebKit content filter.
+pub struct ContentFilters {
+    settings: Rc<RefCell<Settings>>,
+    filter: RefCell<Option<UserContentFilter>>,
+}
+
+impl ContentFilters {
+    pub fn new(settings: Rc<RefCell<Settings>>) -> Self {
+        Self {
+            settings,
+            filter: RefCell::new(None),
+        }
+    }
+
+    /// Compile filter lists, or load the filter compiled last time if the
+    /// lists have not been changed since.
+    pub async fn load(&self) {
+        if !self.settings.borrow().content_blocking.enabled {
+            return;
+        }
+        let lists = read_lists();
+        if lists.is_empty() {
+            return;
+        }
+        let cache_dir = match settings::get_app_cache_dir() {
+            Some(dir) => dir.join(CACHE_DIR_NAME),
+            None => return,
+        };
+        if let Err(e) = std::fs::create_dir_all(&cache_dir) {
+            eprintln!("failed to create {}: {}", cache_dir.display(), e);
+            return;
+        }
+        let data = format!("{}\n{}", CONVERTER_VERSION, lists.concat());
+        let checksum = glib::compute_checksum_for_data(glib::ChecksumType::Sha256, data.as_bytes())
+            .map(|checksum| checksum.to_string())
+            .unwrap_or_default();
+        let checksum_path = cache_dir.join(CHECKSUM_FILE_NAME);
+        let store = UserContentFilterStore::new(&cache_dir.to_string_lossy());
+
+        if std::fs::read_to_string(&checksum_path).ok().as_deref() == Some(checksum.as_str()) {
+            match store.load_future(FILTER_ID).await {
+                Ok(filter) => {
+                    self.filter.replace(Some(filter));
+                    return;
+                }
+                Err(e) => eprintln!("failed to load compiled content filter: {}", e),
+            }
+        }
+
+        let rules = convert(&lists);
+        match store
+            .save_future(FILTER_ID, &glib::Bytes::from_owned(rules.into_bytes()))
+            .await
+        {
+            Ok(filter) => {
+                self.filter.replace(Some(filter));
+                if let Err(e) = std::fs::write(&checksum_path, &checksum) {
+                    eprintln!("failed to write {}: {}", checksum_path.display(), e);
+                }
+            }
+            Err(e) => eprintln!("failed to compile content filter: {}", e),
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.filter.borrow().is_some()
+    }
+
+    /// Whether blocking is turned off for the site of `uri`.
+    pub fn is_disabled_for(&self, uri: &str) -> bool {
+        match rules::host(uri) {
+            Some(host) => self
+                .settings
+                .borrow()
+                .content_blocking
+                .disabled_hosts
+                .contains(&host),
+            None => false,
+        }
+    }
+
+    /// Turn blocking for the site of `uri` off, or back on.
+    pub fn toggle_site(&self, uri: &str) {
+        let host = match rules::host(uri) {
+            Some(host) => host,
+            None => return,
+        };
+        let mut settings = self.settings.borrow_mut();
+        let disabled_hosts = &mut settings.content_blocking.disabled_hosts;
+        if let Some(i) = disabled_hosts.iter().position(|h| *h == host) {
+            disabled_hosts.remove(i);
+        } else {
+            disabled_hosts.push(host);
+            disabled_hosts.sort();
+        }
+    }
+
+    /// Attach or detach the filter for the page `webview` is going to show.
+    pub fn apply(&self, webview: &WebView, uri: &str) {
+        let filter = self.filter.borrow();
+        let (filter, manager) = match (filter.as_ref(), webview.user_content_manager()) {
+            (Some(filter), Some(manager)) => (filter, manager),
+            _ => return,
+        };
+        manager.remove_filter(filter);
+        if !self.is_disabled_for(uri) {
+            manager.add_filter(filter);
+        }
+    }
+
+    /// Apply the filter to all open windows.
+    pub fn apply_all(&self, app: &Application) {
+        for win in app.windows() {
+            if let Some(viewer) = win.child().and_downcast::<Viewer>() {
+                let webview = viewer.webview();
+                self.apply(&webview, &webview.uri().unwrap_or_default());
+            }
+        }
+    }
+}
+
+fn read_lists() -> Vec<String> {
+    let dir = match settings::get_app_config_dir() {
+        Some(dir) => dir.join(FILTERS_DIR_NAME),
+        None => return Vec::new(),
+    };
+    let mut paths: Vec<_> = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+    // keep the order stable, for the checksum
+    paths.sort();
+    paths
+        .iter()
+        .filter_map(|path| match std::fs::read(path) {
+            Ok(data) => Some(String::from_utf8_lossy(&data).into_owned()),
+            Err(e) => {
+                eprintln!("failed to read {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+struct Rule {
+    trigger: Trigger,
+    action: Action,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Trigger {
+    url_filter: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    url_filter_is_case_sensitive: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    resource_type: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    load_type: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    if_domain: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unless_domain: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Action {
+    #[serde(rename = "type")]
+    type_: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    selector: Option<String>,
+}
+
+/// Convert filter lists in EasyList (Adblock Plus) or hosts file syntax to
+/// WebKit content blocker rules in JSON.
+///
+/// Rules WebKit can't express, such as regular expressions and most of
+/// extended CSS, are skipped.
+fn convert(lists: &[String]) -> String {
+    let mut blocks = Vec::new();
+    let mut hides = Vec::new();
+    let mut exceptions = Vec::new();
+    for line in lists.iter().flat_map(|list| list.lines()) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('!') || line.starts_with('[') {
+            continue;
+        }
+        if let Some(rule) = parse_hosts_line(line) {
+            blocks.push(rule);
+        } else if line.contains("#@#") || line.contains("#?#") || line.contains("#$#") {
+            continue;
+        } else if line.contains("##") {
+            hides.extend(parse_hiding_rule(line));
+        } else if line.starts_with('#') {
+            // comment of a hosts file
+            continue;
+        } else if let Some(line) = line.strip_prefix("@@") {
+            exceptions.extend(parse_network_rule(line, "ignore-previous-rules"));
+        } else {
+            blocks.extend(parse_network_rule(line, "block"));
+        }
+    }
+    // exceptions only cancel the rules before them
+    blocks.extend(hides);
+    blocks.extend(exceptions);
+    serde_json::to_string(&blocks).unwrap_or_else(|e| {
+        dbg!(e);
+        "[]".to_owned()
+    })
+}
+
+// "0.0.0.0 example.com"
+fn parse_hosts_line(line: &str) -> Option<Rule> {
+    let mut fields = line.split_whitespace();
+    let address = fields.next()?;
+    if !matches!(address, "0.0.0.0" | "127.0.0.1" | "::" | "::1") {
+        return None;
+    }
+    let host = fields.next()?.to_lowercase();
+    if matches!(
+        host.as_str(),
+        "localhost" | "localhost.localdomain" | "local" | "broadcasthost" | "0.0.0.0"
+    ) || !host.is_ascii()
+    {
+        return None;
+    }
+    Some(Rule {
+        trigger: Trigger {
+            url_filter: format!("^[^:]+://+([^:/]+\\.)?{}[:/]", escape(&host)),
+            ..Default::default()
+        },
+        action: Action {
+            type_: "block",
+            selector: None,
+        },
+    })
+}
+
+// "example.com,~foo.example.com##.ad"
+fn parse_hiding_rule(line: &str) -> Option<Rule> {
+    let (domains, selector) = line.split_once("##")?;
+    // a selector WebKit can't parse would fail the whole filter
+    if selector.is_empty()
+        || selector.starts_with(char::is_whitespace)
+        || selector.starts_with('+')
+        || selector.contains(":-abp-")
+    {
+        return None;
+    }
+    let mut trigger = Trigger {
+        url_filter: ".*".to_owned(),
+        ..Default::default()
+    };
+    for domain in domains.split(',').filter(|d| !d.is_empty()) {
+        match domain.strip_prefix('~') {
+            Some(domain) => trigger
+                .unless_domain
+                .push(format!("*{}", domain.to_lowercase())),
+            None => trigger
+                .if_domain
+                .push(format!("*{}", domain.to_lowercase())),
+        }
+    }
+    // WebKit doesn't allow both
+    if !trigger.if_domain.is_empty() {
+        trigger.unless_domain.clear();
+    }
+    Some(Rule {
+        trigger,
+        action: Action {
+            type_: "css-display-none",
+            selector: Some(selector.to_owned()),
+        },
+    })
+}
+
+// "||example.com^$script,third-party"
+fn parse_network_rule(line: &str, action: &'static str) -> Option<Rule> {
+    // regular expressions can't be converted reliably
+    if line.starts_with('/') && line.ends_with('/') && line.len() > 1 {
+        return None;
+    }
+    let (pattern, options) = match line.rfind('$') {
+        Some(i) if is_options(&line[i + 1..]) => (&line[..i], Some(&line[i + 1..])),
+        _ => (line, None),
+    };
+    let mut trigger = Trigger {
+        url_filter: pattern_to_regex(pattern)?,
+        ..Default::default()
+    };
+    for option in options.into_iter().flat_map(|options| options.split(',')) {
+        let (name, value) = option.split_once('=').unwrap_or((option, ""));
+        match name {
+            "third-party" | "3p" => trigger.load_type = vec!["third-party"],
+            "~third-party" | "~3p" | "first-party" | "1p" => {
+                trigger.load_type = vec!["first-party"]
+            }
+            "match-case" => trigger.url_filter_is_case_sensitive = true,
+            "domain" => {
+                for domain in value.split('|').filter(|d| !d.is_empty()) {
+                    match domain.strip_prefix('~') {
+                        Some(domain) => trigger
+                            .unless_domain
+                            .push(format!("*{}", domain.to_lowercase())),
+                        None => trigger
+                            .if_domain
+                            .push(format!("*{}", domain.to_lowercase())),
+                    }
+                }
+            }
+            "script" => trigger.resource_type.push("script"),
+            "image" => trigger.resource_type.push("image"),
+            "stylesheet" => trigger.resource_type.push("style-sheet"),
+            "font" => trigger.resource_type.push("font"),
+            "media" => trigger.resource_type.push("media"),
+            "popup" => trigger.resource_type.push("popup"),
+            "subdocument" => trigger.resource_type.push("document"),
+            "xmlhttprequest" | "xhr" | "websocket" | "ping" | "object" | "other" => {
+                trigger.resource_type.push("raw")
+            }
+            // everything else changes what the rule means, so drop the rule
+            _ => return None,
+        }
+    }
+    trigger.resource_type.sort();
+    trigger.resource_type.dedup();
+    if !trigger.if_domain.is_empty() {
+        trigger.unless_domain.clear();
+    }
+    Some(Rule {
+        trigger,
+        action: Action {
+            type_: action,
+            selector: None,
+        },
+    })
+}
+
+fn is_options(text: &str) -> bool {
+    !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "~=,|.-_".contains(c))
+}
+
+fn pattern_to_regex(pattern: &str) -> Option<String> {
+    let mut regex = String::new();
+    let mut rest = pattern;
+    if let Some(r) = rest.strip_prefix("||") {
+        regex.push_str("^[^:]+://+([^:/]+\\.)?");
+        rest = r;
+    } else if let Some(r) = rest.strip_prefix('|') {
+        regex.push('^');
+        rest = r;
+    }
+    let (rest, anchored_end) = match rest.strip_suffix('|') {
+        Some(r) => (r, true),
+        None => (rest, false),
+    };
+    for c in rest.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            // separator: URLs always have "/" after the host, so end of the URL can be left out
+            '^' => regex.push_str("[/:?=&]"),
+            c if !c.is_ascii() => return None,
+            c => regex.push_str(&escape(&c.to_string())),
+        }
+    }
+    if anchored_end {
+        regex.push('$');
+    }
+    if regex.is_empty() || regex == ".*" {
+        // matches everything, surely a mistake
+        return None;
+    }
+    Some(regex)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if ".+?$^{}()[]\\|*".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn rules(list: &str) -> Vec<Value> {
+        match serde_json::from_str(&convert(&[list.to_owned()])) {
+            Ok(Value::Array(rules)) => rules,
+            other => panic!("not an array: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn hosts_file() {
+        let rules =
+            rules("# comment\n0.0.0.0 Ads.Example.com\n127.0.0.1 localhost\n::1 tracker.example\n");
+        assert_eq!(rules.len(), 2);
+        assert_eq!(
+            rules[0],
+            json!({
+                "trigger": {"url-filter": "^[^:]+://+([^:/]+\\.)?ads\\.example\\.com[:/]"},
+                "action": {"type": "block"},
+            })
+        );
+        assert_eq!(
+            rules[1]["trigger"]["url-filter"],
+            "^[^:]+://+([^:/]+\\.)?tracker\\.example[:/]"
+        );
+    }
+
+    #[test]
+    fn network_rules() {
+        let rules = rules("||ads.example.com^$script,third-party\n|https://x.example/*.js|\n");
+        assert_eq!(
+            rules[0],
+            json!({
+                "trigger": {
+                    "url-filter": "^[^:]+://+([^:/]+\\.)?ads\\.example\\.com[/:?=&]",
+                    "resource-type": ["script"],
+                    "load-type": ["third-party"],
+                },
+                "action": {"type": "block"},
+            })
+        );
+        assert_eq!(
+            rules[1]["trigger"]["url-filter"],
+            "^https://x\\.example/.*\\.js$"
+        );
+    }
+
+    #[test]
+    fn domains() {
+        let rules = rules("/banner/$domain=a.example|~b.a.example\n/x/$domain=~c.example\n");
+        // WebKit doesn't allow both if-domain and unless-domain
+        assert_eq!(rules[0]["trigger"]["if-domain"], json!(["*a.example"]));
+        assert!(rules[0]["trigger"].get("unless-domain").is_none());
+        assert_eq!(rules[1]["trigger"]["unless-domain"], json!(["*c.example"]));
+    }
+
+    #[test]
+    fn hiding_and_exceptions_come_last() {
+        let rules = rules("@@||good.example^\nexample.com##.ad\n||bad.example^\n");
+        let types: Vec<&Value> = rules.iter().map(|rule| &rule["action"]["type"]).collect();
+        assert_eq!(
+            types,
+            ["block", "css-display-none", "ignore-previous-rules"]
+        );
+        assert_eq!(rules[1]["action"]["selector"], ".ad");
+        assert_eq!(rules[1]["trigger"]["if-domain"], json!(["*example.com"]));
+    }
+
+    #[test]
+    fn skipped() {
+        let list = [
+            "! comment",
+            "[Adblock Plus 2.0]",
+            // regular expression
+            "/ads[0-9]+/",
+            // unknown option changes the meaning
+            "||example.com^$csp=default-src",
+            "||example.com^$redirect=noop.js",
+            // extended CSS
+            "example.com#?#.ad:has(.x)",
+            "example.com#@#.ad",
+            "example.com##+js(foo)",
+            // matches everything
+            "*",
+            // non-ASCII
+            "||例え.jp^",
+        ]
+        .join("\n");
+        assert_eq!(rules(&list), Vec::<Value>::new());
+    }
+}
@@ -0,0 +1,641 @@
+use gtk4 as gtk;
+
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use serde_derive::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use webkit6::prelude::*;
+use webkit6::{Download, DownloadError, WebView};
+
+use crate::rules;
+use crate::settings::{self, DownloadRule, Settings};
+
+const DOWNLOADS_FILE_NAME: &'static str = "downloads.toml";
+const MAX_DOWNLOADS: usize = 50;
+// extensions kept whole when numbering file names
+const COMPOUND_EXTENSIONS: &[&'static str] = &[".tar.gz", ".tar.xz", ".tar.bz2", ".tar.zst"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Active,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// identifies the entry while running, not saved
+    #[serde(skip)]
+    pub id: u32,
+    pub uri: String,
+    /// path of the file, once decided
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    pub status: Status,
+    /// reason of the failure
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub error: String,
+    /// unix time the download was started
+    #[serde(default)]
+    pub started: i64,
+    #[serde(skip)]
+    pub progress: f64,
+    #[serde(skip)]
+    pub received: u64,
+    /// expected size, 0 if unknown
+    #[serde(skip)]
+    pub total: u64,
+}
+
+impl Entry {
+    /// File name of the destination, or the last part of the URI.
+    pub fn name(&self) -> String {
+        match &self.destination {
+            Some(destination) => Path::new(destination)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| destination.clone()),
+            None => self
+                .uri
+                .rsplit('/')
+                .find(|part| !part.is_empty())
+                .unwrap_or(&self.uri)
+                .to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DownloadsFile {
+    #[serde(default)]
+    downloads: Vec<Entry>,
+}
+
+/// Downloads of all windows, the most recent first, kept across restarts.
+pub struct Downloads {
+    settings: Rc<RefCell<Settings>>,
+    entries: RefCell<Vec<Entry>>,
+    // running downloads by entry id
+    running: RefCell<Vec<(u32, Download)>>,
+    next_id: Cell<u32>,
+    // URI and destination of the download being retried
+    retrying: RefCell<Option<(String, Option<String>)>>,
+    // called on changes, until they return Break
+    handlers: RefCell<Vec<Box<dyn Fn() -> glib::ControlFlow>>>,
+}
+
+impl Downloads {
+    pub fn load(settings: Rc<RefCell<Settings>>) -> Self {
+        let mut entries = match settings::load_config_file(DOWNLOADS_FILE_NAME) {
+            Some(data) => match toml::from_str::<DownloadsFile>(&data) {
+                Ok(file) => file.downloads,
+                Err(e) => {
+                    dbg!(e);
+                    Vec::new()
+                }
+            },
+            None => Vec::new(),
+        };
+        for (i, entry) in entries.iter_mut().enumerate() {
+            entry.id = i as u32 + 1;
+            // wv quit while downloading
+            if entry.status == Status::Active {
+                entry.status = Status::Failed;
+                entry.error = "interrupted".to_owned();
+            }
+        }
+        Self {
+            settings,
+            next_id: Cell::new(entries.len() as u32 + 1),
+            entries: RefCell::new(entries),
+            running: RefCell::new(Vec::new()),
+            retrying: RefCell::new(None),
+            handlers: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn save(&self) {
+        let file = DownloadsFile {
+            downloads: self.entries.borrow().clone(),
+        };
+        match toml::to_string(&file) {
+            Ok(data) => settings::save_config_file(DOWNLOADS_FILE_NAME, &data),
+            Err(e) => {
+                dbg!(e);
+            }
+        }
+    }
+
+    pub fn entries(&self) -> Vec<Entry> {
+        self.entries.borrow().clone()
+    }
+
+    /// Call `f` whenever downloads change, until it returns Break.
+    pub fn connect_changed<F: Fn() -> glib::ControlFlow + 'static>(&self, f: F) {
+        self.handlers.borrow_mut().push(Box::new(f));
+    }
+
+    fn notify(&self) {
+        // handlers may look at entries, but not add other handlers
+        let handlers = self.handlers.take();
+        let handlers: Vec<_> = handlers
+            .into_iter()
+            .filter(|handler| handler() == glib::ControlFlow::Continue)
+            .collect();
+        let mut current = self.handlers.borrow_mut();
+        let added = std::mem::replace(&mut *current, handlers);
+        current.extend(added);
+    }
+
+    fn update<F: FnOnce(&mut Entry)>(&self, id: u32, f: F) {
+        if let Some(entry) = self.entries.borrow_mut().iter_mut().find(|e| e.id == id) {
+            f(entry);
+        }
+    }
+
+    /// Keep track of `download`, started by a page of `parent`.
+    pub fn add(self: &Rc<Self>, download: &Download, parent: &gtk::Window) {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        let uri = download
+            .request()
+            .and_then(|request| request.uri())
+            .map(|uri| uri.to_string())
+            .unwrap_or_default();
+        let retry_destination = match self.retrying.take() {
+            Some((retry_uri, destination)) if retry_uri == uri => destination,
+            _ => None,
+        };
+        if retry_destination.is_some() {
+            // replace the partial file
+            download.set_allow_overwrite(true);
+        }
+        {
+            let mut entries = self.entries.borrow_mut();
+            entries.insert(
+                0,
+                Entry {
+                    id,
+                    uri,
+                    destination: None,
+                    status: Status::Active,
+                    error: String::new(),
+                    started: glib::real_time() / 1_000_000,
+                    progress: 0.0,
+                    received: 0,
+                    total: 0,
+                },
+            );
+            // forget old ones, but not running ones
+            let mut kept = 0;
+            entries.retain(|entry| {
+                kept += 1;
+                kept <= MAX_DOWNLOADS || entry.status == Status::Active
+            });
+        }
+        self.running.borrow_mut().push((id, download.clone()));
+
+        let parent = parent.downgrade();
+        let settings = self.settings.clone();
+        // the empty file created by automatic_destination
+        let placeholder: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
+        let decided_placeholder = placeholder.clone();
+        download.connect_decide_destination(move |download, suggested_filename| {
+            if let Some(destination) = &retry_destination {
+                download.set_destination(destination);
+                return true;
+            }
+            let destination = automatic_destination(
+                &settings.borrow().downloads.rules,
+                download,
+                suggested_filename,
+            );
+            if let Some(destination) = destination {
+                // replace the empty file reserving the name
+                download.set_allow_overwrite(true);
+                download.set_destination(&destination.to_string_lossy());
+                decided_placeholder.replace(Some(destination));
+                return true;
+            }
+            let suggested_filename = suggested_filename.to_owned();
+            let parent = parent.upgrade();
+            let download = download.clone();
+            glib::MainContext::default().spawn_local(async move {
+                let dialog = gtk::FileDialog::builder()
+                    .title("Download File")
+                    .initial_name(&suggested_filename)
+                    .build();
+                if let Some(download_folder) =
+                    glib::user_special_dir(glib::UserDirectory::Downloads)
+                {
+                    dialog.set_initial_folder(Some(&gio::File::for_path(&download_folder)));
+                }
+                if let Ok(file) = dialog.save_future(parent.as_ref()).await {
+                    if let Some(path) = file.path() {
+                        download.set_destination(&path.to_string_lossy());
+                    } else {
+                        eprintln!("path is None for {}", file.uri());
+                        download.cancel();
+                    }
+                } else {
+                    download.cancel();
+                }
+            });
+
+            true
+        });
+        download.connect_created_destination(glib::clone!(
+            #[weak(rename_to = downloads)]
+            self,
+            move |_download, destination| {
+                // given as a file URI
+                let path = glib::filename_from_uri(destination)
+                    .map(|(path, _)| path.to_string_lossy().into_owned())
+                    .unwrap_or_else(|_| destination.to_owned());
+                downloads.update(id, |entry| entry.destination = Some(path));
+                downloads.save();
+                downloads.notify();
+            }
+        ));
+        download.connect_estimated_progress_notify(glib::clone!(
+            #[weak(rename_to = downloads)]
+            self,
+            move |download| {
+                let progress = download.estimated_progress();
+                let mut changed = false;
+                downloads.update(id, |entry| {
+                    // in steps of a percent, not to rebuild lists too often
+                    changed = (progress * 100.0) as i32 != (entry.progress * 100.0) as i32;
+                    entry.progress = progress;
+                    entry.received = download.received_data_length();
+                    entry.total = download
+                        .response()
+                        .map_or(0, |response| response.content_length());
+                });
+                if changed {
+                    downloads.notify();
+                }
+            }
+        ));
+        download.connect_failed(glib::clone!(
+            #[weak(rename_to = downloads)]
+            self,
+            move |_download, error| {
+                // free the name for a retry or another download
+                if let Some(path) = placeholder.take() {
+                    if let Err(e) = remove_if_empty(&path) {
+                        eprintln!("can't remove {}: {}", path.display(), e);
+                    }
+                }
+                downloads.update(id, |entry| {
+                    if error.matches(DownloadError::CancelledByUser) {
+                        entry.status = Status::Cancelled;
+                    } else {
+                        entry.status = Status::Failed;
+                        entry.error = error.message().to_owned();
+                    }
+                });
+            }
+        ));
+        // after "failed" if it failed
+        download.connect_finished(glib::clone!(
+            #[weak(rename_to = downloads)]
+            self,
+            move |_download| {
+                downloads.update(id, |entry| {
+                    if entry.status == Status::Active {
+                        entry.status = Status::Finished;
+                        entry.progress = 1.0;
+                    }
+                });
+                downloads.running.borrow_mut().retain(|(i, _)| *i != id);
+                downloads.save();
+                downloads.notify();
+            }
+        ));
+
+        self.save();
+        self.notify();
+    }
+
+    pub fn cancel(&self, id: u32) {
+        let download = self
+            .running
+            .borrow()
+            .iter()
+            .find(|(i, _)| *i == id)
+            .map(|(_, download)| download.clone());
+        if let Some(download) = download {
+            download.cancel();
+        }
+    }
+
+    /// Download the URI of a failed or cancelled download again with
+    /// `webview`, into the same file.
+    pub fn retry(&self, id: u32, webview: &WebView) {
+        let entry = match self.entries.borrow().iter().find(|e| e.id == id) {
+            Some(entry) => entry.clone(),
+            None => return,
+        };
+        self.remove(id);
+        // taken by add(), from "download-started" of the session
+        self.retrying
+            .replace(Some((entry.uri.clone(), entry.destination)));
+        webview.download_uri(&entry.uri);
+    }
+
+    /// Forget a download that is not running.
+    pub fn remove(&self, id: u32) {
+        self.entries
+            .borrow_mut()
+            .retain(|entry| entry.id != id || entry.status == Status::Active);
+        self.save();
+        self.notify();
+    }
+
+    /// Forget all downloads that are not running.
+    pub fn clear(&self) {
+        self.entries
+            .borrow_mut()
+            .retain(|entry| entry.status == Status::Active);
+        self.save();
+        self.notify();
+    }
+}
+
+/// Path in the directory of the first rule matching `download`, or None to
+/// ask.
+fn automatic_destination(
+    rules: &[DownloadRule],
+    download: &Download,
+    suggested_filename: &str,
+) -> Option<PathBuf> {
+    let file_name = Path::new(suggested_filename)
+        .file_name()?
+        .to_string_lossy()
+        .into_owned();
+    let mime_type = download
+        .response()
+        .and_then(|response| response.mime_type())
+        .map(|mime_type| mime_type.to_lowercase());
+    let hosts: Vec<String> = [
+        download.request().and_then(|request| request.uri()),
+        download.web_view().and_then(|webview| webview.uri()),
+    ]
+    .iter()
+    .flatten()
+    .filter_map(|uri| rules::host(uri))
+    .collect();
+
+    let rule = find_rule(rules, mime_type.as_deref(), &file_name, &hosts)?;
+
+    let directory = match rule.directory.strip_prefix("~") {
+        Ok(rest) => glib::home_dir().join(rest),
+        Err(_) => match glib::user_special_dir(glib::UserDirectory::Downloads) {
+            Some(downloads) => downloads.join(&rule.directory),
+            None => glib::home_dir().join(&rule.directory),
+        },
+    };
+    if let Err(e) = std::fs::create_dir_all(&directory) {
+        eprintln!("can't create {}: {}", directory.display(), e);
+        return None;
+    }
+    match unique_path(&directory, &file_name) {
+        Ok(path) => Some(path),
+        Err(e) => {
+            eprintln!(
+                "can't create {} in {}: {}",
+                file_name,
+                directory.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+// the first rule all of whose conditions match; `mime_type` and `hosts` are
+// in lower case
+fn find_rule<'a>(
+    rules: &'a [DownloadRule],
+    mime_type: Option<&str>,
+    file_name: &str,
+    hosts: &[String],
+) -> Option<&'a DownloadRule> {
+    rules.iter().find(|rule| {
+        if let Some(pattern) = &rule.mime_type {
+            match mime_type {
+                Some(mime_type) if rules::glob_match(&pattern.to_lowercase(), mime_type) => (),
+                _ => return false,
+            }
+        }
+        if let Some(extension) = &rule.extension {
+            let suffix = format!(".{}", extension.trim_start_matches('.').to_lowercase());
+            if !file_name.to_lowercase().ends_with(&suffix) {
+                return false;
+            }
+        }
+        if let Some(pattern) = &rule.host {
+            let pattern = pattern.to_lowercase();
+            if !hosts.iter().any(|host| rules::glob_match(&pattern, host)) {
+                return false;
+            }
+        }
+        true
+    })
+}
+
+/// `directory/file_name`, or with " (1)", " (2)" and so on before the
+/// extension if it exists. The file is created empty, so that downloads
+/// finishing at the same time don't pick the same name.
+fn unique_path(directory: &Path, file_name: &str) -> std::io::Result<PathBuf> {
+    let (stem, extension) = split_extension(file_name);
+    let mut n = 0;
+    loop {
+        let path = if n == 0 {
+            directory.join(file_name)
+        } else {
+            directory.join(format!("{} ({}){}", stem, n, extension))
+        };
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(_) => return Ok(path),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// "v1.2.3.zip" -> ("v1.2.3", ".zip"), "archive.tar.gz" -> ("archive",
+// ".tar.gz"), but ".bashrc" -> (".bashrc", "")
+fn split_extension(file_name: &str) -> (&str, &str) {
+    let lower = file_name.to_lowercase();
+    for extension in COMPOUND_EXTENSIONS {
+        if lower.len() == file_name.len()
+            && lower.len() > extension.len()
+            && lower.ends_with(extension)
+        {
+            return file_name.split_at(file_name.len() - extension.len());
+        }
+    }
+    match file_name.rfind('.') {
+        Some(i) if i > 0 => file_name.split_at(i),
+        _ => (file_name, ""),
+    }
+}
+
+// remove the file at `path` if nothing was written to it
+fn remove_if_empty(path: &Path) -> std::io::Result<()> {
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.len() == 0 => std::fs::remove_file(path),
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Open the downloaded file with its default application.
+pub fn open(entry: &Entry) {
+    if let Some(destination) = &entry.destination {
+        launch_default(&gio::File::for_path(destination).uri());
+    }
+}
+
+/// Open the folder of the downloaded file.
+pub fn show_in_folder(entry: &Entry) {
+    if let Some(folder) = entry
+        .destination
+        .as_ref()
+        .and_then(|destination| Path::new(destination).parent())
+    {
+        launch_default(&gio::File::for_path(folder).uri());
+    }
+}
+
+fn launch_default(uri: &str) {
+    let context = gtk::gdk::Display::default().map(|d| d.app_launch_context());
+    if let Err(e) = gio::AppInfo::launch_default_for_uri(uri, context.as_ref()) {
+        eprintln!("{:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an empty directory of its own for each test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wv-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn names(directory: &Path, file_name: &str, count: usize) -> Vec<String> {
+        (0..count)
+            .map(|_| {
+                let path = unique_path(directory, file_name).unwrap();
+                assert!(path.exists());
+                path.file_name().unwrap().to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unique_names() {
+        let dir = temp_dir("unique-names");
+        assert_eq!(
+            names(&dir, "archive.tar.gz", 3),
+            ["archive.tar.gz", "archive (1).tar.gz", "archive (2).tar.gz"]
+        );
+        assert_eq!(names(&dir, ".bashrc", 2), [".bashrc", ".bashrc (1)"]);
+        assert_eq!(names(&dir, "README", 2), ["README", "README (1)"]);
+        std::fs::write(dir.join("taken.txt"), "x").unwrap();
+        assert_eq!(names(&dir, "taken.txt", 1), ["taken (1).txt"]);
+        assert_eq!(
+            names(&dir, "v1.2.3.zip", 2),
+            ["v1.2.3.zip", "v1.2.3 (1).zip"]
+        );
+        assert_eq!(
+            names(&dir, "Backup.TAR.XZ", 2),
+            ["Backup.TAR.XZ", "Backup (1).TAR.XZ"]
+        );
+        assert_eq!(names(&dir, ".tar.gz", 2), [".tar.gz", ".tar (1).gz"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn placeholders_removed() {
+        let dir = temp_dir("placeholders-removed");
+        let path = unique_path(&dir, "file.bin").unwrap();
+        remove_if_empty(&path).unwrap();
+        assert!(!path.exists());
+        // the name is free again
+        assert_eq!(names(&dir, "file.bin", 1), ["file.bin"]);
+        // but not if something was downloaded
+        std::fs::write(&path, "partial").unwrap();
+        remove_if_empty(&path).unwrap();
+        assert!(path.exists());
+        remove_if_empty(&dir.join("missing")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn rule(
+        mime_type: Option<&str>,
+        extension: Option<&str>,
+        host: Option<&str>,
+        directory: &str,
+    ) -> DownloadRule {
+        DownloadRule {
+            mime_type: mime_type.map(|s| s.to_owned()),
+            extension: extension.map(|s| s.to_owned()),
+            host: host.map(|s| s.to_owned()),
+            directory: PathBuf::from(directory),
+        }
+    }
+
+    #[test]
+    fn rules() {
+        let rules = [
+            rule(
+                Some("image/*"),
+                None,
+                Some("*.example.com"),
+                "example-images",
+            ),
+            rule(Some("image/*"), None, None, "images"),
+            rule(None, Some(".TAR.GZ"), None, "archives"),
+            rule(None, None, Some("downloads.example.org"), "example"),
+        ];
+        let find = |mime_type, file_name, hosts: &[&str]| {
+            let hosts: Vec<String> = hosts.iter().map(|h| h.to_string()).collect();
+            find_rule(&rules, mime_type, file_name, &hosts)
+                .map(|rule| rule.directory.to_string_lossy().into_owned())
+        };
+        let hosts = ["cdn.example.net", "www.example.com"];
+        assert_eq!(
+            find(Some("image/png"), "a.png", &hosts).as_deref(),
+            Some("example-images")
+        );
+        assert_eq!(
+            find(Some("image/png"), "a.png", &["example.net"]).as_deref(),
+            Some("images")
+        );
+        assert_eq!(find(None, "src.tar.gz", &[]).as_deref(), Some("archives"));
+        // extensions match whole parts
+        assert_eq!(find(None, "srctar.gz", &[]), None);
+        assert_eq!(
+            find(Some("application/pdf"), "a.pdf", &["downloads.example.org"]).as_deref(),
+            Some("example")
+        );
+        // no MIME type doesn't match a rule with one
+        assert_eq!(find(None, "a.png", &["example.net"]), None);
+    }
+}
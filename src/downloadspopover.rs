@@ -0,0 +1,238 @@
+use gtk4 as gtk;
+
+use gtk::prelude::*;
+use gtk::{glib, pango};
+use gtk::{
+    Align, Button, Label, ListBox, MenuButton, Orientation, Popover, ProgressBar, ScrolledWindow,
+};
+use std::cell::RefCell;
+use std::rc::Rc;
+use webkit6::WebView;
+
+use crate::downloads::{self, Entry, Status};
+use crate::state::AppState;
+
+// widgets of a row that change while downloading; the buttons change with
+// the status, so the list is rebuilt for that
+struct Row {
+    id: u32,
+    status: Status,
+    name_label: Label,
+    progress_bar: Option<ProgressBar>,
+    status_label: Label,
+}
+
+/// Popover of `button` listing downloads, shown while there are any.
+/// Retried downloads are started with `webview`.
+pub fn build_downloads_popover(button: &MenuButton, state: Rc<AppState>, webview: &WebView) {
+    let popover = Popover::new();
+    let vbox = gtk::Box::new(Orientation::Vertical, 6);
+    let list_box = ListBox::new();
+    list_box.set_selection_mode(gtk::SelectionMode::None);
+    let scrolled = ScrolledWindow::builder()
+        .child(&list_box)
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .propagate_natural_height(true)
+        .max_content_height(480)
+        .min_content_width(360)
+        .build();
+    vbox.append(&scrolled);
+    let clear_button = Button::builder()
+        .label("Clear")
+        .tooltip_text("forget finished and failed downloads")
+        .halign(Align::End)
+        .build();
+    vbox.append(&clear_button);
+    popover.set_child(Some(&vbox));
+    button.set_popover(Some(&popover));
+
+    let rows: Rc<RefCell<Vec<Row>>> = Rc::new(RefCell::new(Vec::new()));
+    let populate = Rc::new(glib::clone!(
+        #[weak]
+        button,
+        #[weak]
+        popover,
+        #[weak]
+        list_box,
+        #[weak]
+        webview,
+        #[strong]
+        state,
+        #[strong]
+        rows,
+        // stop being called when the window is gone
+        #[upgrade_or]
+        glib::ControlFlow::Break,
+        move || {
+            let entries = state.downloads.entries();
+            button.set_visible(!entries.is_empty());
+            if entries.iter().any(|entry| entry.status == Status::Active) {
+                button.add_css_class("accent");
+            } else {
+                button.remove_css_class("accent");
+            }
+            // updated on show
+            if !popover.is_visible() {
+                return glib::ControlFlow::Continue;
+            }
+            let mut rows = rows.borrow_mut();
+            let unchanged = rows.len() == entries.len()
+                && rows
+                    .iter()
+                    .zip(&entries)
+                    .all(|(row, entry)| row.id == entry.id && row.status == entry.status);
+            if unchanged {
+                for (row, entry) in rows.iter().zip(&entries) {
+                    update_row(row, entry);
+                }
+            } else {
+                list_box.remove_all();
+                rows.clear();
+                for entry in &entries {
+                    let (widget, row) = build_row(entry, &state, &webview);
+                    list_box.append(&widget);
+                    rows.push(row);
+                }
+            }
+            glib::ControlFlow::Continue
+        }
+    ));
+    populate();
+    state.downloads.connect_changed(glib::clone!(
+        #[strong]
+        populate,
+        move || populate()
+    ));
+    popover.connect_show(glib::clone!(
+        #[strong]
+        populate,
+        move |_popover| {
+            populate();
+        }
+    ));
+    clear_button.connect_clicked(glib::clone!(
+        #[strong]
+        state,
+        move |_button| {
+            state.downloads.clear();
+        }
+    ));
+}
+
+fn build_row(entry: &Entry, state: &Rc<AppState>, webview: &WebView) -> (gtk::Box, Row) {
+    let hbox = gtk::Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .margin_top(3)
+        .margin_bottom(3)
+        .margin_start(6)
+        .margin_end(6)
+        .build();
+    let vbox = gtk::Box::new(Orientation::Vertical, 2);
+    vbox.set_hexpand(true);
+    let row = Row {
+        id: entry.id,
+        status: entry.status,
+        name_label: Label::builder()
+            .tooltip_text(&entry.uri)
+            .ellipsize(pango::EllipsizeMode::Middle)
+            .xalign(0.0)
+            .build(),
+        progress_bar: if entry.status == Status::Active {
+            Some(ProgressBar::new())
+        } else {
+            None
+        },
+        status_label: Label::builder()
+            .ellipsize(pango::EllipsizeMode::End)
+            .xalign(0.0)
+            .css_classes(["dim-label"])
+            .build(),
+    };
+    update_row(&row, entry);
+    vbox.append(&row.name_label);
+    if let Some(progress_bar) = &row.progress_bar {
+        vbox.append(progress_bar);
+    }
+    vbox.append(&row.status_label);
+    hbox.append(&vbox);
+
+    let id = entry.id;
+    match entry.status {
+        Status::Active => {
+            let state = state.clone();
+            add_button(&hbox, "process-stop-symbolic", "cancel", move || {
+                state.downloads.cancel(id)
+            });
+        }
+        Status::Finished => {
+            let opened = entry.clone();
+            add_button(&hbox, "document-open-symbolic", "open", move || {
+                downloads::open(&opened)
+            });
+            let shown = entry.clone();
+            add_button(&hbox, "folder-open-symbolic", "show in folder", move || {
+                downloads::show_in_folder(&shown)
+            });
+        }
+        Status::Failed | Status::Cancelled => {
+            let state = state.clone();
+            let webview = webview.downgrade();
+            add_button(&hbox, "view-refresh-symbolic", "retry", move || {
+                if let Some(webview) = webview.upgrade() {
+                    state.downloads.retry(id, &webview);
+                }
+            });
+        }
+    }
+    if entry.status != Status::Active {
+        let state = state.clone();
+        add_button(
+            &hbox,
+            "edit-clear-symbolic",
+            "remove from the list",
+            move || state.downloads.remove(id),
+        );
+    }
+    (hbox, row)
+}
+
+fn update_row(row: &Row, entry: &Entry) {
+    row.name_label.set_label(&entry.name());
+    if let Some(progress_bar) = &row.progress_bar {
+        progress_bar.set_fraction(entry.progress);
+    }
+    let status = status_text(entry);
+    row.status_label.set_label(&status);
+    row.status_label.set_tooltip_text(Some(&status));
+}
+
+fn add_button<F: Fn() + 'static>(hbox: &gtk::Box, icon_name: &str, tooltip: &str, f: F) {
+    let button = Button::builder()
+        .icon_name(icon_name)
+        .tooltip_text(tooltip)
+        .has_frame(false)
+        .valign(Align::Center)
+        .build();
+    button.connect_clicked(move |_button| f());
+    hbox.append(&button);
+}
+
+// "1.2 MB of 3.4 MB", "failed: ..." and so on
+fn status_text(entry: &Entry) -> String {
+    match entry.status {
+        Status::Active if entry.total > 0 => format!(
+            "{} of {}",
+            glib::format_size(entry.received),
+            glib::format_size(entry.total)
+        ),
+        Status::Active if entry.received > 0 => glib::format_size(entry.received).to_string(),
+        Status::Active => "starting".to_owned(),
+        Status::Finished => entry
+            .destination
+            .clone()
+            .unwrap_or_else(|| "finished".to_owned()),
+        Status::Failed => format!("failed: {}", entry.error),
+        Status::Cancelled => "cancelled".to_owned(),
+    }
+}
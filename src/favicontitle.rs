@@ -22,6 +22,8 @@ mod imp {
         pub subtitle: Label,
         #[property(get)]
         pub favicon: Image,
+        #[property(get)]
+        pub entry: gtk::Entry,
     }
     #[glib::object_subclass]
     impl ObjectSubclass for FaviconTitle {
@@ -67,6 +69,13 @@ mod imp {
             let subtitle_box = gtk::Box::new(Orientation::Horizontal, 0);
             self.subtitle.set_hexpand(true);
             subtitle_box.append(&self.subtitle);
+
+            self.entry.add_css_class("location");
+            self.entry.set_hexpand(true);
+            self.entry.set_input_purpose(gtk::InputPurpose::Url);
+            self.entry.set_placeholder_text(Some("URL or search"));
+            self.entry.set_visible(false);
+            subtitle_box.append(&self.entry);
             label_box.append(&subtitle_box);
         }
     }
@@ -110,9 +119,24 @@ impl FaviconTitle {
         }
     }
 
-    pub fn select_subtitle(&self) {
-        let subtitle = &self.subtitle();
-        subtitle.grab_focus();
-        subtitle.select_region(0, -1);
+    /// Replace the subtitle with an entry to edit the URL.
+    pub fn start_editing(&self) {
+        let entry = self.entry();
+        if !entry.is_visible() {
+            entry.set_text(&self.subtitle().label());
+            self.subtitle().set_visible(false);
+            entry.set_visible(true);
+        }
+        entry.grab_focus();
+        entry.select_region(0, -1);
+    }
+
+    pub fn stop_editing(&self) {
+        self.entry().set_visible(false);
+        self.subtitle().set_visible(true);
+    }
+
+    pub fn is_editing(&self) -> bool {
+        self.entry().is_visible()
     }
 }
@@ -0,0 +1,544 @@
+use gtk4 as gtk;
+
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use gtk::{Application, ApplicationCommandLine};
+use std::cell::{Cell, RefCell};
+use std::ffi::OsString;
+use std::io::Read;
+use std::rc::Rc;
+use webkit6::prelude::*;
+use webkit6::{LoadEvent, PrintOperation, SnapshotOptions, SnapshotRegion, WebView};
+
+use crate::options::{DumpFormat, Options};
+use crate::profile::Profile;
+use crate::state::AppState;
+use crate::viewer::Viewer;
+
+// replaced with the number of the URL in output names
+const NUMBER_PLACEHOLDER: &'static str = "{n}";
+
+// how long to wait for a page to finish loading
+const LOAD_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+// how long to wait for `--wait-for`
+const WAIT_FOR_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+const WAIT_FOR_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+const TEXT_SCRIPT: &'static str = "document.body ? document.body.innerText : ''";
+const HTML_SCRIPT: &'static str = "(document.doctype ? \
+     new XMLSerializer().serializeToString(document.doctype) + '\\n' : '') + \
+     document.documentElement.outerHTML";
+const MARKDOWN_SCRIPT: &'static str = r##"
+(() => {
+    const SKIPPED = ["script", "style", "noscript", "template", "head", "svg", "canvas",
+        "iframe", "button", "select", "input", "textarea", "nav"];
+    const BLOCKS = ["address", "article", "aside", "blockquote", "details", "div", "dl",
+        "fieldset", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header",
+        "hr", "li", "main", "ol", "p", "pre", "section", "summary", "table", "ul"];
+
+    function hidden(element) {
+        const style = window.getComputedStyle(element);
+        return style.display === "none" || style.visibility === "hidden";
+    }
+
+    function isBlock(node) {
+        return node.nodeType === Node.ELEMENT_NODE &&
+            BLOCKS.includes(node.tagName.toLowerCase());
+    }
+
+    function wrap(text, mark) {
+        const trimmed = text.trim();
+        return trimmed ? mark + trimmed + mark : "";
+    }
+
+    function inline(node) {
+        if (node.nodeType === Node.TEXT_NODE) {
+            return node.textContent.replace(/\s+/g, " ").replace(/([\\`*_\[\]])/g, "\\$1");
+        }
+        if (node.nodeType !== Node.ELEMENT_NODE || hidden(node)) {
+            return "";
+        }
+        const tag = node.tagName.toLowerCase();
+        if (SKIPPED.includes(tag)) {
+            return "";
+        }
+        const content = () => Array.from(node.childNodes).map(inline).join("");
+        switch (tag) {
+        case "br":
+            return "  \n";
+        case "strong":
+        case "b":
+            return wrap(content(), "**");
+        case "em":
+        case "i":
+            return wrap(content(), "*");
+        case "code":
+        case "kbd":
+        case "samp":
+            return "`" + node.textContent.replace(/`/g, "'") + "`";
+        case "a": {
+            const text = content().trim();
+            const href = node.getAttribute("href");
+            if (!href || href.startsWith("javascript:") || !text) {
+                return text;
+            }
+            return "[" + text + "](" + node.href + ")";
+        }
+        case "img":
+            return node.getAttribute("src") ? "![" + (node.alt || "") + "](" + node.src + ")" : "";
+        default:
+            // blocks inside inline elements, like <div> in <a>
+            return isBlock(node) ? "\n\n" + convert(node) + "\n\n" : content();
+        }
+    }
+
+    // Markdown of the children of `element`, lines after the first indented
+    // with `indent`
+    function blocks(element, indent) {
+        const parts = [];
+        let text = "";
+        const flush = () => {
+            const trimmed = text.replace(/ *\n */g, "\n").trim();
+            if (trimmed) {
+                parts.push(trimmed);
+            }
+            text = "";
+        };
+        for (const node of element.childNodes) {
+            if (isBlock(node) && !hidden(node)) {
+                flush();
+                const converted = convert(node);
+                if (converted) {
+                    parts.push(converted);
+                }
+            } else {
+                text += inline(node);
+            }
+        }
+        flush();
+        return parts.join("\n\n").replace(/\n/g, "\n" + indent);
+    }
+
+    function convert(element) {
+        const tag = element.tagName.toLowerCase();
+        const heading = /^h([1-6])$/.exec(tag);
+        if (heading) {
+            return "#".repeat(Number(heading[1])) + " " +
+                blocks(element, "").replace(/\n+/g, " ");
+        }
+        switch (tag) {
+        case "hr":
+            return "---";
+        case "pre":
+            return "```\n" + element.textContent.replace(/\n$/, "") + "\n```";
+        case "blockquote":
+            return "> " + blocks(element, "> ");
+        case "ul":
+        case "ol": {
+            let number = Number(element.getAttribute("start") || 1);
+            return Array.from(element.children)
+                .filter((child) => child.tagName.toLowerCase() === "li" && !hidden(child))
+                .map((item) => {
+                    const marker = tag === "ol" ? (number++) + ". " : "- ";
+                    return marker + blocks(item, " ".repeat(marker.length));
+                })
+                .join("\n");
+        }
+        case "table": {
+            const rows = Array.from(element.rows).map((row) => Array.from(row.cells)
+                .map((cell) => blocks(cell, "").replace(/\n+/g, " ").replace(/\|/g, "\\|")));
+            if (rows.length === 0) {
+                return "";
+            }
+            const width = Math.max(...rows.map((row) => row.length));
+            const line = (row) =>
+                "| " + Array.from({ length: width }, (_, i) => row[i] || "").join(" | ") + " |";
+            return [line(rows[0]), line(Array(width).fill("---"))]
+                .concat(rows.slice(1).map(line))
+                .join("\n");
+        }
+        default:
+            return blocks(element, "");
+        }
+    }
+
+    const title = document.title ? "# " + document.title + "\n\n" : "";
+    const body = document.body ? blocks(document.body, "") : "";
+    return (title + body).replace(/\n{3,}/g, "\n\n").trim() + "\n";
+})()
+"##;
+
+/// What is made of each page.
+#[derive(Debug, Clone)]
+enum Output {
+    Screenshot {
+        full_page: bool,
+    },
+    Pdf,
+    Dump(DumpFormat),
+    /// JSON of the value of a script
+    Eval(String),
+}
+
+#[derive(Debug)]
+struct Job {
+    uri: String,
+    /// None to print to stdout
+    output: Option<gio::File>,
+}
+
+/// Render the URLs in `args` and `--batch` to files, or print them or the
+/// value of a script run in them, without opening windows of wv, and exit
+/// with `exit_status` set to non-zero if any of them failed.
+///
+/// Returns the exit status of the command line handler.
+pub fn start(
+    app: &Application,
+    state: &Rc<AppState>,
+    options: &Options,
+    cmdline: &ApplicationCommandLine,
+    args: &[OsString],
+    exit_status: Rc<Cell<i32>>,
+) -> i32 {
+    let (template, output) = match (&options.screenshot, &options.pdf, options.dump_format()) {
+        (Some(template), _, _) => (
+            Some(template.as_str()),
+            Output::Screenshot {
+                full_page: options.full_page,
+            },
+        ),
+        (None, Some(template), _) => (Some(template.as_str()), Output::Pdf),
+        (None, None, Some(format)) => (None, Output::Dump(format)),
+        (None, None, None) => match &options.eval {
+            Some(script) => (None, Output::Eval(script.clone())),
+            None => return 0,
+        },
+    };
+    let jobs = match jobs(cmdline, args, options.batch.as_deref(), template) {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            cmdline.printerr_literal(&format!("{}\n", e));
+            return 1;
+        }
+    };
+    let mut profile = options
+        .profile(&state.settings.borrow())
+        .unwrap_or_else(Profile::private);
+    // an ephemeral session of its own whatever the session policy, unless a
+    // profile is asked for
+    if options.profile.is_none() {
+        profile.isolated = true;
+    }
+    let viewer = Viewer::new(None, state, &profile);
+    let webview = viewer.webview();
+    let settings = WebViewExt::settings(&webview).unwrap();
+    if let Some(user_agent) = &options.user_agent {
+        settings.set_user_agent(Some(user_agent.as_str()));
+    }
+    if options.no_javascript {
+        settings.set_enable_javascript(false);
+    }
+    if let Some(zoom) = options.zoom {
+        webview.set_zoom_level(zoom);
+    }
+    let window = gtk::Window::builder()
+        .default_width(options.width.unwrap_or(1280))
+        .default_height(options.height.unwrap_or(800))
+        .decorated(false)
+        .opacity(0.0)
+        .child(&viewer)
+        .build();
+    // the page has to be mapped to be rendered, but there is nothing to see
+    if let Output::Screenshot { .. } | Output::Pdf = output {
+        window.present();
+    }
+
+    let hold_guard = app.hold();
+    let options = options.clone();
+    glib::MainContext::default().spawn_local(glib::clone!(
+        #[strong]
+        cmdline,
+        async move {
+            let _hold_guard = hold_guard;
+            for job in &jobs {
+                if let Err(e) = run_job(&webview, job, &output, &options, &cmdline).await {
+                    cmdline.printerr_literal(&format!("{}: {}\n", job.uri, e));
+                    exit_status.set(1);
+                }
+            }
+            window.destroy();
+        }
+    ));
+    0
+}
+
+// URLs and output files from `args` and lines of `batch`
+fn jobs(
+    cmdline: &ApplicationCommandLine,
+    args: &[OsString],
+    batch: Option<&str>,
+    template: Option<&str>,
+) -> Result<Vec<Job>, String> {
+    let mut entries: Vec<(String, Option<String>)> = args
+        .iter()
+        .map(|arg| (cmdline.create_file_for_arg(arg).uri().to_string(), None))
+        .collect();
+    if let Some(batch) = batch {
+        let list = if batch == "-" {
+            let mut list = String::new();
+            std::io::stdin()
+                .read_to_string(&mut list)
+                .map_err(|e| format!("failed to read stdin: {}", e))?;
+            list
+        } else {
+            let path = cmdline.create_file_for_arg(batch).path();
+            std::fs::read_to_string(path.as_deref().unwrap_or(batch.as_ref()))
+                .map_err(|e| format!("failed to read {}: {}", batch, e))?
+        };
+        for line in list.lines().map(|line| line.trim()) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            if let Some(uri) = fields.next() {
+                entries.push((
+                    cmdline.create_file_for_arg(uri).uri().to_string(),
+                    fields.next().map(|output| output.to_owned()),
+                ));
+            }
+        }
+    }
+    if entries.is_empty() {
+        return Err("no URL given".to_owned());
+    }
+    let template = match template {
+        Some(template) => template,
+        None => {
+            return Ok(entries
+                .into_iter()
+                .map(|(uri, _)| Job { uri, output: None })
+                .collect())
+        }
+    };
+    let numbered = template.contains(NUMBER_PLACEHOLDER);
+    if !numbered
+        && entries
+            .iter()
+            .filter(|(_, output)| output.is_none())
+            .count()
+            > 1
+    {
+        return Err(format!(
+            "output name needs \"{}\" for several URLs",
+            NUMBER_PLACEHOLDER
+        ));
+    }
+    Ok(entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, (uri, output))| {
+            let output = output
+                .unwrap_or_else(|| template.replace(NUMBER_PLACEHOLDER, &(i + 1).to_string()));
+            Job {
+                uri,
+                output: Some(cmdline.create_file_for_arg(output)),
+            }
+        })
+        .collect())
+}
+
+async fn run_job(
+    webview: &WebView,
+    job: &Job,
+    output: &Output,
+    options: &Options,
+    cmdline: &ApplicationCommandLine,
+) -> Result<(), String> {
+    load(webview, &job.uri).await?;
+    if let Some(selector) = &options.wait_for {
+        wait_for(webview, selector).await?;
+    }
+    if let Some(delay) = options.delay {
+        glib::timeout_future(std::time::Duration::from_secs_f64(delay)).await;
+    }
+    match (output, &job.output) {
+        (Output::Dump(format), _) => {
+            let script = match format {
+                DumpFormat::Text => TEXT_SCRIPT,
+                DumpFormat::Html => HTML_SCRIPT,
+                DumpFormat::Markdown => MARKDOWN_SCRIPT,
+            };
+            let value = webview
+                .evaluate_javascript_future(script, None, None)
+                .await
+                .map_err(|e| e.to_string())?;
+            let mut text = value.to_str().to_string();
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+            cmdline.print_literal(&text);
+            Ok(())
+        }
+        (Output::Eval(script), _) => {
+            let json = evaluate(webview, script).await?;
+            cmdline.print_literal(&format!("{}\n", json));
+            Ok(())
+        }
+        (Output::Screenshot { full_page }, Some(file)) => {
+            let region = if *full_page {
+                SnapshotRegion::FullDocument
+            } else {
+                SnapshotRegion::Visible
+            };
+            let texture = webview
+                .snapshot_future(region, SnapshotOptions::NONE)
+                .await
+                .map_err(|e| e.to_string())?;
+            let path = file
+                .path()
+                .ok_or_else(|| format!("not a local file: {}", file.uri()))?;
+            texture.save_to_png(&path).map_err(|e| e.to_string())
+        }
+        (Output::Pdf, Some(file)) => print_pdf(webview, file).await,
+        (_, None) => Err("no output file".to_owned()),
+    }
+}
+
+/// Run `script` in the page of `webview`, returning its value as JSON, or
+/// the exception it threw.
+pub async fn evaluate(webview: &WebView, script: &str) -> Result<String, String> {
+    let value = webview
+        .evaluate_javascript_future(script, None, None)
+        .await
+        .map_err(|e| e.message().to_owned())?;
+    // undefined and functions have no JSON
+    Ok(value
+        .to_json(0)
+        .map(|json| json.to_string())
+        .unwrap_or_else(|| "null".to_owned()))
+}
+
+// poll for an element matching `selector`, as it may be added by scripts
+async fn wait_for(webview: &WebView, selector: &str) -> Result<(), String> {
+    let script = format!(
+        "document.querySelector({}) !== null",
+        serde_json::to_string(selector).unwrap_or_default()
+    );
+    let start = std::time::Instant::now();
+    loop {
+        // an invalid selector throws
+        let found = webview
+            .evaluate_javascript_future(&script, None, None)
+            .await
+            .map_err(|e| e.to_string())?;
+        if found.to_boolean() {
+            return Ok(());
+        }
+        if start.elapsed() > WAIT_FOR_TIMEOUT {
+            return Err(format!("timed out waiting for {}", selector));
+        }
+        glib::timeout_future(WAIT_FOR_INTERVAL).await;
+    }
+}
+
+/// Load `uri` in `webview` and wait until it's finished, failing for errors
+/// of the network and of HTTP.
+pub async fn load(webview: &WebView, uri: &str) -> Result<(), String> {
+    let handlers = Rc::new(RefCell::new(Vec::new()));
+    let timeout: Rc<Cell<Option<glib::SourceId>>> = Rc::new(Cell::new(None));
+    let uri = uri.to_owned();
+    let result = gio::GioFuture::new(webview, {
+        let handlers = handlers.clone();
+        let timeout = timeout.clone();
+        move |webview: &WebView, _cancellable, send| {
+            let send = Rc::new(RefCell::new(Some(send)));
+            let failed_send = send.clone();
+            let timeout_send = send.clone();
+            // "load-failed" comes before "load-changed" with Finished
+            handlers.borrow_mut().push(webview.connect_load_failed(
+                move |_webview, _event, _uri, error| {
+                    if let Some(send) = failed_send.borrow_mut().take() {
+                        send.resolve(Err(error.to_string()));
+                    }
+                    false
+                },
+            ));
+            handlers
+                .borrow_mut()
+                .push(webview.connect_load_changed(move |_webview, event| {
+                    if event == LoadEvent::Finished {
+                        if let Some(send) = send.borrow_mut().take() {
+                            send.resolve(Ok(()));
+                        }
+                    }
+                }));
+            // a stalled server would otherwise keep wv running forever
+            timeout.set(Some(glib::timeout_add_local_once(
+                LOAD_TIMEOUT,
+                glib::clone!(
+                    #[strong]
+                    timeout,
+                    #[weak]
+                    webview,
+                    move || {
+                        timeout.take();
+                        if let Some(send) = timeout_send.borrow_mut().take() {
+                            webview.stop_loading();
+                            send.resolve(Err(format!(
+                                "timed out after {} seconds",
+                                LOAD_TIMEOUT.as_secs()
+                            )));
+                        }
+                    }
+                ),
+            )));
+            webview.load_uri(&uri);
+        }
+    })
+    .await;
+    for handler in handlers.take() {
+        webview.disconnect(handler);
+    }
+    if let Some(source) = timeout.take() {
+        source.remove();
+    }
+    result?;
+    match webview
+        .main_resource()
+        .and_then(|resource| resource.response())
+        .map(|response| response.status_code())
+    {
+        Some(status) if status >= 400 => Err(format!("HTTP status {}", status)),
+        _ => Ok(()),
+    }
+}
+
+async fn print_pdf(webview: &WebView, output: &gio::File) -> Result<(), String> {
+    let print_settings = gtk::PrintSettings::new();
+    print_settings.set_printer("Print to File");
+    print_settings.set(gtk::PRINT_SETTINGS_OUTPUT_FILE_FORMAT.as_str(), Some("pdf"));
+    print_settings.set(
+        gtk::PRINT_SETTINGS_OUTPUT_URI.as_str(),
+        Some(output.uri().as_str()),
+    );
+    let operation = PrintOperation::new(webview);
+    operation.set_print_settings(&print_settings);
+    gio::GioFuture::new(&operation, |operation, _cancellable, send| {
+        let send = Rc::new(RefCell::new(Some(send)));
+        let failed_send = send.clone();
+        // "failed" comes before "finished"
+        operation.connect_failed(move |_operation, error| {
+            if let Some(send) = failed_send.borrow_mut().take() {
+                send.resolve(Err(error.to_string()));
+            }
+        });
+        operation.connect_finished(move |_operation| {
+            if let Some(send) = send.borrow_mut().take() {
+                send.resolve(Ok(()));
+            }
+        });
+        operation.print();
+    })
+    .await
+}
@@ -0,0 +1,256 @@
+use gtk4 as gtk;
+
+use gtk::glib;
+use serde_derive::Deserialize;
+use webkit6::prelude::*;
+use webkit6::{
+    UserContentInjectedFrames, UserContentManager, UserScript, UserScriptInjectionTime, WebView,
+};
+
+// scripts in this world are out of reach of the page's own scripts
+const WORLD_NAME: &'static str = "wv-hints";
+const MESSAGE_HANDLER_NAME: &'static str = "wvHints";
+
+const HINTS_SCRIPT: &'static str = r#"
+(() => {
+    const ALPHABET = "asdfghjklqwertyuiopzxcvbnm";
+    const SELECTOR = "a[href], area[href], button, input:not([type=hidden]), select, " +
+        "textarea, summary, [onclick], [role=link], [role=button], [tabindex]:not([tabindex='-1'])";
+    let hints = null;
+    let typed = "";
+    // "follow", "window" or "browser"
+    let mode = "follow";
+
+    function visible(element) {
+        const rect = element.getClientRects()[0];
+        if (!rect || rect.width === 0 || rect.height === 0) {
+            return null;
+        }
+        if (rect.bottom < 0 || rect.right < 0 ||
+            rect.top > window.innerHeight || rect.left > window.innerWidth) {
+            return null;
+        }
+        const style = window.getComputedStyle(element);
+        if (style.visibility === "hidden" || style.display === "none") {
+            return null;
+        }
+        return rect;
+    }
+
+    // labels of the same length, so none is a prefix of another
+    function labels(count) {
+        let length = 1;
+        while (Math.pow(ALPHABET.length, length) < count) {
+            length++;
+        }
+        const result = [];
+        for (let i = 0; i < count; i++) {
+            let label = "";
+            for (let n = i, j = 0; j < length; j++) {
+                label = ALPHABET[n % ALPHABET.length] + label;
+                n = Math.floor(n / ALPHABET.length);
+            }
+            result.push(label);
+        }
+        return result;
+    }
+
+    function stop() {
+        if (hints) {
+            hints.container.remove();
+            hints = null;
+        }
+        typed = "";
+        document.removeEventListener("keydown", onKeyDown, true);
+    }
+
+    function follow(element, target) {
+        stop();
+        const uri = element.href;
+        if (uri && target !== "follow") {
+            window.webkit.messageHandlers.wvHints.postMessage({
+                uri: String(uri),
+                title: (element.innerText || element.title || "").trim(),
+                target,
+            });
+            return;
+        }
+        const tag = element.tagName.toLowerCase();
+        if ((tag === "input" && !["button", "submit", "reset", "checkbox", "radio",
+                "image", "file"].includes(element.type)) ||
+            tag === "textarea" || tag === "select" || element.isContentEditable) {
+            element.focus();
+            if (element.select) {
+                element.select();
+            }
+            return;
+        }
+        element.focus();
+        element.click();
+    }
+
+    function update() {
+        let matches = 0;
+        for (const hint of hints.items) {
+            const match = hint.label.startsWith(typed);
+            hint.marker.style.display = match ? "" : "none";
+            hint.marker.firstChild.textContent = hint.label.slice(0, typed.length);
+            hint.marker.lastChild.textContent = hint.label.slice(typed.length);
+            if (match) {
+                matches++;
+            }
+        }
+        return matches;
+    }
+
+    function onKeyDown(event) {
+        if (event.key === "Shift" || event.key === "Alt") {
+            return;
+        }
+        event.preventDefault();
+        event.stopImmediatePropagation();
+        if (event.key === "Escape") {
+            stop();
+            return;
+        }
+        if (event.key === "Backspace") {
+            typed = typed.slice(0, -1);
+            update();
+            return;
+        }
+        const key = event.key.toLowerCase();
+        if (key.length !== 1 || !ALPHABET.includes(key)) {
+            return;
+        }
+        typed += key;
+        const hint = hints.items.find((hint) => hint.label === typed);
+        if (hint) {
+            // modifiers of the last key override the mode hints were started in
+            const target = event.altKey ? "browser" : event.shiftKey ? "window" : mode;
+            follow(hint.element, target);
+        } else if (update() === 0) {
+            stop();
+        }
+    }
+
+    function start(startMode) {
+        stop();
+        mode = startMode || "follow";
+        const elements = [];
+        for (const element of document.querySelectorAll(SELECTOR)) {
+            const rect = visible(element);
+            if (rect && !element.disabled) {
+                elements.push([element, rect]);
+            }
+        }
+        if (elements.length === 0) {
+            return;
+        }
+        const container = document.createElement("div");
+        container.style.cssText = "position: fixed; inset: 0; pointer-events: none; " +
+            "z-index: 2147483647;";
+        const items = labels(elements.length).map((label, i) => {
+            const [element, rect] = elements[i];
+            const marker = document.createElement("span");
+            marker.style.cssText = "position: absolute; padding: 0 2px; " +
+                "font: bold 11px monospace; text-transform: uppercase; " +
+                "color: #302505; background: #ffd76e; border: 1px solid #c38a22; " +
+                "border-radius: 2px; box-shadow: 0 1px 3px rgba(0, 0, 0, 0.3);";
+            marker.style.left = Math.max(rect.left, 0) + "px";
+            marker.style.top = Math.max(rect.top, 0) + "px";
+            const typedPart = document.createElement("span");
+            typedPart.style.opacity = "0.4";
+            marker.append(typedPart, document.createElement("span"));
+            marker.lastChild.textContent = label;
+            container.append(marker);
+            return { label, element, marker };
+        });
+        document.documentElement.append(container);
+        hints = { container, items };
+        document.addEventListener("keydown", onKeyDown, true);
+    }
+
+    window.wvHints = { start, stop };
+    window.addEventListener("scroll", stop, { passive: true });
+    window.addEventListener("resize", stop);
+})();
+"#;
+
+/// Where a link picked with a hint is opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HintTarget {
+    /// followed in the page
+    Follow,
+    /// a new wv window, or Shift with the last key
+    Window,
+    /// the preferred external browser, or Alt with the last key
+    Browser,
+}
+
+impl HintTarget {
+    fn name(self) -> &'static str {
+        match self {
+            HintTarget::Follow => "follow",
+            HintTarget::Window => "window",
+            HintTarget::Browser => "browser",
+        }
+    }
+}
+
+/// A link to open outside of the page.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Hint {
+    pub uri: String,
+    #[serde(default)]
+    pub title: String,
+    pub target: HintTarget,
+}
+
+/// Add the hint script to `manager`, and call `f` with links to open in a
+/// new window or a browser.
+pub fn attach<F: Fn(Hint) + 'static>(manager: &UserContentManager, f: F) {
+    manager.add_script(&UserScript::for_world(
+        HINTS_SCRIPT,
+        UserContentInjectedFrames::TopFrame,
+        UserScriptInjectionTime::End,
+        WORLD_NAME,
+        &[],
+        &[],
+    ));
+    manager.connect_script_message_received(Some(MESSAGE_HANDLER_NAME), move |_manager, value| {
+        let json = match value.to_json(0) {
+            Some(json) => json,
+            None => return,
+        };
+        match serde_json::from_str::<Hint>(&json) {
+            Ok(hint) => f(hint),
+            Err(e) => eprintln!("invalid hint message {}: {}", json, e),
+        }
+    });
+    if !manager.register_script_message_handler(MESSAGE_HANDLER_NAME, Some(WORLD_NAME)) {
+        eprintln!(
+            "failed to register {} message handler",
+            MESSAGE_HANDLER_NAME
+        );
+    }
+}
+
+/// Show hints on the links and form fields visible in `webview`.
+pub fn start(webview: &WebView, target: HintTarget) {
+    // keys are read by the page
+    webview.grab_focus();
+    let webview = webview.clone();
+    let script = format!(
+        "window.wvHints && window.wvHints.start(\"{}\")",
+        target.name()
+    );
+    glib::MainContext::default().spawn_local(async move {
+        if let Err(e) = webview
+            .evaluate_javascript_future(&script, Some(WORLD_NAME), None)
+            .await
+        {
+            eprintln!("failed to start hints: {:?}", e);
+        }
+    });
+}
@@ -0,0 +1,238 @@
+use gtk4 as gtk;
+
+use gtk::gio::prelude::*;
+use gtk::gio::{Cancellable, File};
+use gtk::{gio, glib};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::profile::Profile;
+use crate::settings::Settings;
+
+const HISTORY_FILE_NAME: &'static str = "history.tsv";
+
+// seconds to wait for more visits before writing the file
+const SAVE_DELAY: u32 = 5;
+
+/// Whether visits to `uri` are worth recording.
+pub fn is_recorded(uri: &str) -> bool {
+    !uri.starts_with("about:") && !uri.starts_with("data:")
+}
+
+/// A visited page.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub uri: String,
+    pub title: String,
+    /// unix time of the last visit
+    pub last_visit: i64,
+    pub visit_count: u32,
+}
+
+impl Entry {
+    // one line per entry: "LAST_VISIT<TAB>VISIT_COUNT<TAB>URI<TAB>TITLE"
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(4, '\t');
+        let last_visit = fields.next()?.parse().ok()?;
+        let visit_count = fields.next()?.parse().ok()?;
+        let uri = fields.next()?.to_owned();
+        let title = fields.next().unwrap_or_default().to_owned();
+        Some(Self {
+            uri,
+            title,
+            last_visit,
+            visit_count,
+        })
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\n",
+            self.last_visit,
+            self.visit_count,
+            self.uri,
+            self.title.replace(['\t', '\r', '\n'], " ")
+        )
+    }
+}
+
+/// History of a profile, the most recently visited first.
+pub struct HistoryStore {
+    path: PathBuf,
+    settings: Rc<RefCell<Settings>>,
+    entries: RefCell<Vec<Entry>>,
+    save_scheduled: Cell<bool>,
+}
+
+impl HistoryStore {
+    fn load(path: PathBuf, settings: Rc<RefCell<Settings>>) -> Self {
+        let mut entries = Vec::new();
+        match File::for_path(&path).load_contents(Cancellable::NONE) {
+            Ok((data, _)) => {
+                for line in String::from_utf8_lossy(&data).lines() {
+                    match Entry::parse(line) {
+                        Some(entry) => entries.push(entry),
+                        None => eprintln!("broken history line: {:?}", line),
+                    }
+                }
+            }
+            Err(e) if e.matches(gio::IOErrorEnum::NotFound) => (),
+            Err(e) => {
+                dbg!(e);
+            }
+        }
+        let store = Self {
+            path,
+            settings,
+            entries: RefCell::new(Vec::new()),
+            save_scheduled: Cell::new(false),
+        };
+        store.prune(&mut entries);
+        store.entries.replace(entries);
+        store
+    }
+
+    /// Record a visit to `uri`.
+    pub fn visit(self: &Rc<Self>, uri: &str) {
+        let mut entries = self.entries.borrow_mut();
+        let mut entry = match entries.iter().position(|entry| entry.uri == uri) {
+            Some(i) => entries.remove(i),
+            None => Entry {
+                uri: uri.to_owned(),
+                title: String::new(),
+                last_visit: 0,
+                visit_count: 0,
+            },
+        };
+        entry.last_visit = now();
+        entry.visit_count += 1;
+        entries.insert(0, entry);
+        self.prune(&mut entries);
+        drop(entries);
+        self.schedule_save();
+    }
+
+    /// Update the title of a visited page.
+    pub fn set_title(self: &Rc<Self>, uri: &str, title: &str) {
+        let mut entries = self.entries.borrow_mut();
+        match entries.iter_mut().find(|entry| entry.uri == uri) {
+            Some(entry) if entry.title != title => entry.title = title.to_owned(),
+            _ => return,
+        }
+        drop(entries);
+        self.schedule_save();
+    }
+
+    /// Entries whose URI or title contains `text`, at most `limit`.
+    pub fn search(&self, text: &str, limit: usize) -> Vec<Entry> {
+        let text = text.to_lowercase();
+        self.entries
+            .borrow()
+            .iter()
+            .filter(|entry| {
+                text.is_empty()
+                    || entry.uri.to_lowercase().contains(&text)
+                    || entry.title.to_lowercase().contains(&text)
+            })
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries.borrow_mut().clear();
+        self.save();
+    }
+
+    // drop entries beyond the retention limits
+    fn prune(&self, entries: &mut Vec<Entry>) {
+        let settings = self.settings.borrow();
+        let history = &settings.history;
+        if history.max_days > 0 {
+            let oldest = now() - history.max_days as i64 * 24 * 60 * 60;
+            entries.retain(|entry| entry.last_visit >= oldest);
+        }
+        if history.max_entries > 0 {
+            entries.truncate(history.max_entries);
+        }
+    }
+
+    fn schedule_save(self: &Rc<Self>) {
+        if self.save_scheduled.replace(true) {
+            return;
+        }
+        let store = Rc::downgrade(self);
+        glib::timeout_add_seconds_local_once(SAVE_DELAY, move || {
+            if let Some(store) = store.upgrade() {
+                store.save();
+            }
+        });
+    }
+
+    pub fn save(&self) {
+        self.save_scheduled.set(false);
+        if let Some(dir) = self.path.parent() {
+            if let Err(e) = std::fs::create_dir_all(dir) {
+                eprintln!("failed to create {}: {}", dir.display(), e);
+                return;
+            }
+        }
+        let data: String = self.entries.borrow().iter().map(Entry::to_line).collect();
+        if let Err(e) = File::for_path(&self.path).replace_contents(
+            data.as_bytes(),
+            None,
+            false,
+            gio::FileCreateFlags::PRIVATE,
+            Cancellable::NONE,
+        ) {
+            dbg!(e);
+        }
+    }
+}
+
+/// History stores of all profiles, loaded on demand.
+pub struct History {
+    settings: Rc<RefCell<Settings>>,
+    stores: RefCell<HashMap<PathBuf, Rc<HistoryStore>>>,
+}
+
+impl History {
+    pub fn new(settings: Rc<RefCell<Settings>>) -> Self {
+        Self {
+            settings,
+            stores: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// History of `profile`.
+    ///
+    /// Returns None if history is disabled, or the profile is private or isolated.
+    pub fn store_for(&self, profile: &Profile) -> Option<Rc<HistoryStore>> {
+        if !self.settings.borrow().history.enabled {
+            return None;
+        }
+        let path = profile.data_dir()?.join(HISTORY_FILE_NAME);
+        let store = self
+            .stores
+            .borrow_mut()
+            .entry(path.clone())
+            .or_insert_with(|| Rc::new(HistoryStore::load(path, self.settings.clone())))
+            .clone();
+        Some(store)
+    }
+
+    /// Write pending changes of all stores.
+    pub fn save_all(&self) {
+        for store in self.stores.borrow().values() {
+            if store.save_scheduled.get() {
+                store.save();
+            }
+        }
+    }
+}
+
+fn now() -> i64 {
+    glib::real_time() / 1_000_000
+}
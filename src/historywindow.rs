@@ -0,0 +1,186 @@
+use gtk4 as gtk;
+
+use gtk::prelude::*;
+use gtk::{gdk, glib};
+use gtk::{Button, HeaderBar, Label, ListBox, Orientation, ScrolledWindow, SearchEntry};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::history::HistoryStore;
+
+// rows shown at once; narrow down with the search entry to see others
+const MAX_ROWS: usize = 500;
+
+/// Show `store` in a searchable window, calling `open` with the URI of the
+/// activated page.
+pub fn present<F: Fn(&str) + 'static>(
+    parent: &impl IsA<gtk::Window>,
+    profile_name: &str,
+    store: Rc<HistoryStore>,
+    open: F,
+) {
+    let win = gtk::Window::builder()
+        .title(format!("History - {}", profile_name))
+        .transient_for(parent)
+        .default_width(640)
+        .default_height(480)
+        .build();
+
+    let header = HeaderBar::new();
+    let clear_button = Button::from_icon_name("edit-clear-all-symbolic");
+    clear_button.set_tooltip_text(Some("clear history"));
+    header.pack_end(&clear_button);
+    win.set_titlebar(Some(&header));
+
+    let vbox = gtk::Box::new(Orientation::Vertical, 0);
+    let search_entry = SearchEntry::builder()
+        .placeholder_text("Search history")
+        .margin_top(6)
+        .margin_bottom(6)
+        .margin_start(6)
+        .margin_end(6)
+        .build();
+    vbox.append(&search_entry);
+
+    let list_box = ListBox::new();
+    list_box.set_activate_on_single_click(true);
+    let scrolled = ScrolledWindow::builder()
+        .child(&list_box)
+        .vexpand(true)
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .build();
+    vbox.append(&scrolled);
+    win.set_child(Some(&vbox));
+
+    let uris: Rc<RefCell<Vec<String>>> = Rc::new(RefCell::new(Vec::new()));
+    let populate = Rc::new(glib::clone!(
+        #[weak]
+        list_box,
+        #[weak]
+        search_entry,
+        #[strong]
+        store,
+        #[strong]
+        uris,
+        move || {
+            list_box.remove_all();
+            let mut uris = uris.borrow_mut();
+            uris.clear();
+            for entry in store.search(&search_entry.text(), MAX_ROWS) {
+                list_box.append(&build_row(
+                    &entry.title,
+                    &entry.uri,
+                    entry.last_visit,
+                    entry.visit_count,
+                ));
+                uris.push(entry.uri);
+            }
+        }
+    ));
+    populate();
+
+    search_entry.connect_search_changed(glib::clone!(
+        #[strong]
+        populate,
+        move |_entry| {
+            populate();
+        }
+    ));
+    list_box.connect_row_activated(glib::clone!(
+        #[strong]
+        uris,
+        move |_list_box, row| {
+            let uri = uris.borrow().get(row.index() as usize).cloned();
+            if let Some(uri) = uri {
+                open(&uri);
+            }
+        }
+    ));
+    clear_button.connect_clicked(glib::clone!(
+        #[weak]
+        win,
+        #[strong]
+        store,
+        #[strong]
+        populate,
+        move |_button| {
+            let dialog = gtk::AlertDialog::builder()
+                .message("Clear all history?")
+                .detail("Visited pages of this profile will be forgotten.")
+                .buttons(["Cancel", "Clear"])
+                .cancel_button(0)
+                .default_button(0)
+                .build();
+            glib::MainContext::default().spawn_local(glib::clone!(
+                #[strong]
+                store,
+                #[strong]
+                populate,
+                async move {
+                    if let Ok(1) = dialog.choose_future(Some(&win)).await {
+                        store.clear();
+                        populate();
+                    }
+                }
+            ));
+        }
+    ));
+
+    let key_controller = gtk::EventControllerKey::new();
+    key_controller.connect_key_pressed(glib::clone!(
+        #[weak]
+        win,
+        #[upgrade_or]
+        glib::Propagation::Proceed,
+        move |_controller, key, _keycode, _state| {
+            if key == gdk::Key::Escape {
+                win.close();
+                glib::Propagation::Stop
+            } else {
+                glib::Propagation::Proceed
+            }
+        }
+    ));
+    win.add_controller(key_controller);
+
+    win.present();
+    search_entry.grab_focus();
+}
+
+fn build_row(title: &str, uri: &str, last_visit: i64, visit_count: u32) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(Orientation::Vertical)
+        .margin_top(3)
+        .margin_bottom(3)
+        .margin_start(6)
+        .margin_end(6)
+        .build();
+    let title_label = Label::builder()
+        .label(if title.is_empty() { uri } else { title })
+        .ellipsize(gtk::pango::EllipsizeMode::End)
+        .xalign(0.0)
+        .build();
+    vbox.append(&title_label);
+
+    let hbox = gtk::Box::new(Orientation::Horizontal, 12);
+    let uri_label = Label::builder()
+        .label(uri)
+        .ellipsize(gtk::pango::EllipsizeMode::Middle)
+        .hexpand(true)
+        .xalign(0.0)
+        .css_classes(["dim-label"])
+        .build();
+    hbox.append(&uri_label);
+    let visited = glib::DateTime::from_unix_local(last_visit)
+        .and_then(|time| time.format("%Y-%m-%d %H:%M"))
+        .map(|time| time.to_string())
+        .unwrap_or_default();
+    let visited_label = Label::builder()
+        .label(visited)
+        .tooltip_text(format!("visited {} times", visit_count))
+        .css_classes(["dim-label"])
+        .build();
+    hbox.append(&visited_label);
+    vbox.append(&hbox);
+    vbox
+}
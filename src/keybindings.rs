@@ -0,0 +1,333 @@
+use gtk4 as gtk;
+
+use gtk::prelude::*;
+use gtk::{Application, ShortcutsGroup, ShortcutsSection, ShortcutsShortcut, ShortcutsWindow};
+use std::collections::{BTreeMap, HashMap};
+
+/// An action that can be bound to keys in the `[keybindings]` table.
+#[derive(Debug)]
+pub struct Action {
+    /// "win.*" or "app.*", the key in the table
+    pub name: &'static str,
+    /// target of the action, in GVariant text format
+    pub target: Option<&'static str>,
+    pub default: &'static [&'static str],
+    pub group: &'static str,
+    pub description: &'static str,
+}
+
+impl Action {
+    /// The name with target, as used by `set_accels_for_action`.
+    pub fn detailed_name(&self) -> String {
+        match self.target {
+            Some(target) => format!("{}({})", self.name, target),
+            None => self.name.to_owned(),
+        }
+    }
+}
+
+const fn action(
+    name: &'static str,
+    default: &'static [&'static str],
+    group: &'static str,
+    description: &'static str,
+) -> Action {
+    Action {
+        name,
+        target: None,
+        default,
+        group,
+        description,
+    }
+}
+
+pub const ACTIONS: &[Action] = &[
+    action("win.new-window", &["<Primary>n"], "Windows", "New window"),
+    action(
+        "win.new-isolated-window",
+        &["<Primary><Shift>n"],
+        "Windows",
+        "New isolated window",
+    ),
+    action("win.close", &["<Primary>w"], "Windows", "Close window"),
+    Action {
+        name: "app.reopen-closed",
+        target: Some("uint32 0"),
+        default: &["<Primary><Shift>t"],
+        group: "Windows",
+        description: "Reopen closed window",
+    },
+    action(
+        "win.reopen-preferred",
+        &["<Primary><Shift>o"],
+        "Windows",
+        "Re-open page with preferred browser",
+    ),
+    action(
+        "win.palette",
+        &["<Primary><Shift>p"],
+        "Windows",
+        "Command palette",
+    ),
+    action(
+        "win.shortcuts",
+        &["<Primary>question"],
+        "Windows",
+        "Keyboard shortcuts",
+    ),
+    action("win.back", &["<alt>Left"], "Navigation", "Go back"),
+    action("win.forward", &["<alt>Right"], "Navigation", "Go forward"),
+    action("win.reload", &["<Primary>r"], "Navigation", "Reload"),
+    action(
+        "win.select-url",
+        &["<Primary>l"],
+        "Navigation",
+        "Edit location",
+    ),
+    action("win.history", &["<Primary>h"], "Navigation", "Show history"),
+    action(
+        "win.bookmark",
+        &["<Primary>d"],
+        "Navigation",
+        "Bookmark this page",
+    ),
+    action(
+        "win.bookmarks",
+        &["<Primary>b"],
+        "Navigation",
+        "Show bookmarks",
+    ),
+    action(
+        "win.hints",
+        &["<Primary>j"],
+        "Navigation",
+        "Follow link with hints",
+    ),
+    action(
+        "win.hints-new-window",
+        &["<Primary><Shift>j"],
+        "Navigation",
+        "Open link in new window with hints",
+    ),
+    action(
+        "win.hints-browser",
+        &["<Primary><Alt>j"],
+        "Navigation",
+        "Open link in preferred browser with hints",
+    ),
+    action("win.find", &["<Primary>f"], "Find", "Find in page"),
+    action(
+        "win.find-selection",
+        &["<Primary>e"],
+        "Find",
+        "Find selected text",
+    ),
+    action(
+        "win.zoom-in",
+        &["<Primary>plus", "<Primary>equal", "<Primary>KP_Add"],
+        "Zoom",
+        "Zoom in",
+    ),
+    action(
+        "win.zoom-out",
+        &["<Primary>minus", "<Primary>KP_Subtract"],
+        "Zoom",
+        "Zoom out",
+    ),
+    action(
+        "win.zoom-reset",
+        &["<Primary>0", "<Primary>KP_0"],
+        "Zoom",
+        "Reset zoom",
+    ),
+    action("win.zoom-text-only", &[], "Zoom", "Zoom text only"),
+    action(
+        "win.toggle-content-blocking",
+        &[],
+        "Page",
+        "Toggle content blocking on this site",
+    ),
+];
+
+/// Accelerators of every action: defaults, replaced by those in
+/// `keybindings`.
+///
+/// Unknown actions and invalid accelerators are reported and ignored. When
+/// an accelerator is bound to several actions, one in `keybindings` wins over
+/// a default; between two in `keybindings`, the first one wins.
+pub fn resolve(keybindings: &BTreeMap<String, Vec<String>>) -> Vec<(&'static Action, Vec<String>)> {
+    resolve_with(keybindings, |accel| {
+        let (key, modifiers) = gtk::accelerator_parse(accel)?;
+        Some(gtk::accelerator_name(key, modifiers).to_string())
+    })
+}
+
+// `normalize` returns the canonical form of an accelerator, or None if it is
+// invalid; GTK's needs GTK initialized, so tests pass their own
+fn resolve_with<F: Fn(&str) -> Option<String>>(
+    keybindings: &BTreeMap<String, Vec<String>>,
+    normalize: F,
+) -> Vec<(&'static Action, Vec<String>)> {
+    for name in keybindings.keys() {
+        if !ACTIONS.iter().any(|action| action.name == name.as_str()) {
+            eprintln!("unknown action in [keybindings]: {}", name);
+        }
+    }
+
+    // accelerator (normalized) -> action name and whether it was configured
+    let mut bound: HashMap<String, (&'static str, bool)> = HashMap::new();
+    let mut resolved: Vec<(&'static Action, Vec<String>)> =
+        ACTIONS.iter().map(|action| (action, Vec::new())).collect();
+    // configured ones first, so that defaults give way to them
+    for configured in [true, false] {
+        for (action, accels) in resolved.iter_mut() {
+            let candidates: Vec<String> = match (configured, keybindings.get(action.name)) {
+                (true, Some(configured_accels)) => configured_accels.clone(),
+                (false, None) => action.default.iter().map(|s| s.to_string()).collect(),
+                _ => continue,
+            };
+            for accel in candidates {
+                let normalized = match normalize(&accel) {
+                    Some(normalized) => normalized,
+                    None => {
+                        eprintln!("invalid accelerator for {}: {}", action.name, accel);
+                        continue;
+                    }
+                };
+                match bound.get(&normalized) {
+                    Some((other, true)) if configured => {
+                        eprintln!(
+                            "{} is bound to both {} and {}, ignored for {}",
+                            accel, other, action.name, action.name
+                        );
+                        continue;
+                    }
+                    Some(_) => continue,
+                    None => (),
+                }
+                bound.insert(normalized, (action.name, configured));
+                accels.push(accel);
+            }
+        }
+    }
+    resolved
+}
+
+/// Set accelerators of every action on `app`.
+pub fn apply(app: &Application, keybindings: &BTreeMap<String, Vec<String>>) {
+    for (action, accels) in resolve(keybindings) {
+        let accels: Vec<&str> = accels.iter().map(|s| s.as_str()).collect();
+        app.set_accels_for_action(&action.detailed_name(), &accels);
+    }
+}
+
+/// Show a window listing the active bindings.
+pub fn present_shortcuts(
+    parent: &impl IsA<gtk::Window>,
+    keybindings: &BTreeMap<String, Vec<String>>,
+) {
+    let section = ShortcutsSection::builder()
+        .section_name("shortcuts")
+        .max_height(12)
+        .build();
+    let mut groups: Vec<(&str, ShortcutsGroup)> = Vec::new();
+    for (action, accels) in resolve(keybindings) {
+        if accels.is_empty() {
+            continue;
+        }
+        let group = match groups.iter().find(|(name, _)| *name == action.group) {
+            Some((_, group)) => group.clone(),
+            None => {
+                let group = ShortcutsGroup::builder().title(action.group).build();
+                section.add_group(&group);
+                groups.push((action.group, group.clone()));
+                group
+            }
+        };
+        group.add_shortcut(
+            &ShortcutsShortcut::builder()
+                .title(action.description)
+                .accelerator(accels.join(" "))
+                .build(),
+        );
+    }
+    let window = ShortcutsWindow::builder()
+        .transient_for(parent)
+        .modal(true)
+        .build();
+    window.add_section(&section);
+    window.present();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(keybindings: &[(&str, &[&str])]) -> HashMap<&'static str, Vec<String>> {
+        let keybindings: BTreeMap<String, Vec<String>> = keybindings
+            .iter()
+            .map(|(name, accels)| {
+                let accels = accels.iter().map(|s| s.to_string()).collect();
+                (name.to_string(), accels)
+            })
+            .collect();
+        // stand-in for GTK: case-insensitive, no spaces
+        resolve_with(&keybindings, |accel| {
+            Some(accel.to_lowercase()).filter(|accel| !accel.is_empty() && !accel.contains(' '))
+        })
+        .into_iter()
+        .map(|(action, accels)| (action.name, accels))
+        .collect()
+    }
+
+    #[test]
+    fn defaults() {
+        let resolved = resolve(&[]);
+        assert_eq!(resolved.len(), ACTIONS.len());
+        assert_eq!(resolved["win.find"], ["<Primary>f"]);
+        assert_eq!(resolved["win.zoom-text-only"], Vec::<String>::new());
+    }
+
+    #[test]
+    fn configured_replace_defaults() {
+        let resolved = resolve(&[("win.find", &["<Primary>g", "F3"])]);
+        assert_eq!(resolved["win.find"], ["<Primary>g", "F3"]);
+        // unbound
+        let resolved = resolve(&[("win.find", &[])]);
+        assert_eq!(resolved["win.find"], Vec::<String>::new());
+    }
+
+    #[test]
+    fn configured_win_over_defaults() {
+        // spelled differently from the default of win.new-window
+        let resolved = resolve(&[("win.find", &["<primary>N"])]);
+        assert_eq!(resolved["win.find"], ["<primary>N"]);
+        assert_eq!(resolved["win.new-window"], Vec::<String>::new());
+    }
+
+    #[test]
+    fn first_configured_wins() {
+        let resolved = resolve(&[("win.find", &["F3"]), ("win.close", &["F3", "<Primary>q"])]);
+        // in the order of ACTIONS, where win.close comes first
+        assert_eq!(resolved["win.close"], ["F3", "<Primary>q"]);
+        assert_eq!(resolved["win.find"], Vec::<String>::new());
+    }
+
+    #[test]
+    fn invalid_and_unknown_ignored() {
+        let resolved = resolve(&[
+            ("win.reload", &["not valid", "F3"]),
+            ("win.no-such-action", &["<Primary>f"]),
+        ]);
+        assert_eq!(resolved["win.reload"], ["F3"]);
+        assert!(!resolved.contains_key("win.no-such-action"));
+        // the default is not taken by the unknown action
+        let bound: Vec<&str> = resolved
+            .iter()
+            .filter(|(_, accels)| accels.iter().any(|accel| accel == "<Primary>f"))
+            .map(|(name, _)| *name)
+            .collect();
+        assert_eq!(bound, ["win.find"]);
+        assert_eq!(resolved["win.find"], ["<Primary>f"]);
+    }
+}
@@ -0,0 +1,159 @@
+use gtk4 as gtk;
+
+use gtk::gio::AppInfo;
+use gtk::glib::GString;
+use gtk::prelude::*;
+use gtk::{gdk, gio, glib};
+use std::ffi::{OsStr, OsString};
+use webkit6::prelude::*;
+use webkit6::WebView;
+
+use crate::settings::{Command, Reopen};
+
+/// Something a page or a link can be handed to.
+#[derive(Debug, Clone)]
+pub enum Target {
+    App(AppInfo),
+    Command(Command),
+}
+
+impl Target {
+    pub fn label(&self) -> String {
+        match self {
+            Target::App(info) => info.name().to_string(),
+            Target::Command(command) => command.label.clone(),
+        }
+    }
+
+    pub fn icon(&self) -> Option<gio::Icon> {
+        match self {
+            Target::App(info) => info.icon(),
+            Target::Command(command) => command
+                .icon
+                .as_ref()
+                .map(|name| gio::ThemedIcon::new(name).upcast()),
+        }
+    }
+
+    /// Hand `url` to this target.
+    ///
+    /// `webview` is used to get the selected text.
+    pub fn launch(&self, webview: &WebView, url: &str, title: &str) {
+        match self {
+            Target::App(info) => launch_app(info, url),
+            Target::Command(command) => run_command(command, webview, url, title),
+        }
+    }
+}
+
+pub fn launch_app(info: &AppInfo, uri: &str) {
+    let context = gdk::Display::default().map(|d| d.app_launch_context());
+    if let Err(e) = info.launch_uris(&[uri], context.as_ref()) {
+        eprintln!("{:?}", e);
+    }
+}
+
+/// Browsers installed, ordered and filtered by settings.
+pub fn browsers(reopen: &Reopen) -> Vec<AppInfo> {
+    let mut browsers: Vec<AppInfo> = AppInfo::recommended_for_type("x-scheme-handler/http")
+        .into_iter()
+        .filter(|info| {
+            match info.id() {
+                // skip myself
+                Some(id) if id == GString::from("wv.desktop") => false,
+                Some(id) => !reopen.hidden.iter().any(|hidden| *hidden == id.as_str()),
+                None => true,
+            }
+        })
+        .collect();
+    // stable sort keeps the order of browsers not listed in settings
+    browsers.sort_by_key(|info| {
+        info.id()
+            .and_then(|id| reopen.browsers.iter().position(|b| *b == id.as_str()))
+            .unwrap_or(reopen.browsers.len())
+    });
+    browsers
+}
+
+pub fn targets(reopen: &Reopen) -> Vec<Target> {
+    browsers(reopen)
+        .into_iter()
+        .map(Target::App)
+        .chain(reopen.commands.iter().cloned().map(Target::Command))
+        .collect()
+}
+
+/// The preferred browser, or the first one if not configured.
+pub fn preferred_browser(reopen: &Reopen) -> Option<AppInfo> {
+    let browsers = browsers(reopen);
+    if let Some(preferred) = &reopen.preferred {
+        if let Some(info) = browsers.iter().find(|info| {
+            info.id()
+                .map_or(false, |id| id.as_str() == preferred.as_str())
+        }) {
+            return Some(info.clone());
+        }
+    }
+    browsers.into_iter().next()
+}
+
+fn run_command(command: &Command, webview: &WebView, url: &str, title: &str) {
+    let command = command.clone();
+    let url = url.to_owned();
+    let title = title.to_owned();
+    let needs_selection = [&command.command, &command.clipboard]
+        .iter()
+        .any(|template| {
+            template
+                .as_deref()
+                .map_or(false, |t| t.contains("{selection}"))
+        });
+    glib::MainContext::default().spawn_local(glib::clone!(
+        #[weak]
+        webview,
+        async move {
+            let selection = if needs_selection {
+                match webview
+                    .evaluate_javascript_future("window.getSelection().toString()", None, None)
+                    .await
+                {
+                    Ok(value) => value.to_str().to_string(),
+                    Err(e) => {
+                        eprintln!("failed to get selection: {:?}", e);
+                        String::new()
+                    }
+                }
+            } else {
+                String::new()
+            };
+            let expand = |template: &str| {
+                template
+                    .replace("{url}", &url)
+                    .replace("{title}", &title)
+                    .replace("{selection}", &selection)
+            };
+
+            if let Some(template) = &command.command {
+                match glib::shell_parse_argv(template) {
+                    Ok(argv) => {
+                        // expand after parsing, not to let the page inject arguments
+                        let argv: Vec<OsString> = argv
+                            .iter()
+                            .map(|arg| OsString::from(expand(&arg.to_string_lossy())))
+                            .collect();
+                        let argv: Vec<&OsStr> = argv.iter().map(|arg| arg.as_os_str()).collect();
+                        if let Err(e) = gio::Subprocess::newv(&argv, gio::SubprocessFlags::NONE) {
+                            eprintln!("failed to run {:?}: {:?}", template, e);
+                        }
+                    }
+                    Err(e) => eprintln!("invalid command {:?}: {:?}", template, e),
+                }
+            }
+            if let Some(template) = &command.clipboard {
+                if let Some(display) = gdk::Display::default() {
+                    display.clipboard().set_text(&expand(template));
+                }
+            }
+        }
+    ));
+}
@@ -0,0 +1,301 @@
+use gtk4 as gtk;
+
+use gtk::glib::{self, Uri};
+use gtk::prelude::*;
+use gtk::{Align, Application, Label, ListBox, Orientation, Popover};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+use webkit6::prelude::*;
+
+use crate::history::HistoryStore;
+use crate::settings::Search;
+use crate::viewer::Viewer;
+
+const MAX_SUGGESTIONS: usize = 10;
+
+// schemes which are not followed by "//"
+const OPAQUE_SCHEMES: &[&str] = &["about", "data", "javascript", "mailto", "view-source"];
+
+/// Turn what is typed in the location entry into a URL.
+pub fn resolve(input: &str, search: &Search) -> String {
+    let input = input.trim();
+    if let Some((keyword, query)) = input.split_once(' ') {
+        if let Some(template) = search.keywords.get(keyword) {
+            return expand(template, query.trim());
+        }
+    }
+    if let Some(scheme) = Uri::parse_scheme(input) {
+        if input.contains("://") || OPAQUE_SCHEMES.contains(&scheme.as_str()) {
+            return input.to_owned();
+        }
+    }
+    if !input.contains(char::is_whitespace) {
+        if input == "localhost"
+            || input.starts_with("localhost:")
+            || input.starts_with("localhost/")
+        {
+            return format!("http://{}", input);
+        }
+        if input.contains('.') {
+            return format!("https://{}", input);
+        }
+    }
+    expand(&search.default, input)
+}
+
+fn expand(template: &str, query: &str) -> String {
+    template.replace("{}", &Uri::escape_string(query, None, true))
+}
+
+/// Pages in back/forward lists of open windows and in `history` matching
+/// `text`, as (title, URI).
+pub fn suggestions(
+    app: &Application,
+    history: Option<&HistoryStore>,
+    text: &str,
+) -> Vec<(String, String)> {
+    let text = text.to_lowercase();
+    let mut seen = HashSet::new();
+    let mut suggestions = Vec::new();
+    for win in app.windows() {
+        let back_forward_list = match win
+            .child()
+            .and_downcast::<Viewer>()
+            .and_then(|viewer| viewer.webview().back_forward_list())
+        {
+            Some(list) => list,
+            None => continue,
+        };
+        let items = back_forward_list
+            .current_item()
+            .into_iter()
+            .chain(back_forward_list.back_list().into_iter().rev())
+            .chain(back_forward_list.forward_list());
+        for item in items {
+            let uri = match item.uri() {
+                Some(uri) => uri.to_string(),
+                None => continue,
+            };
+            let title = item.title().map(|t| t.to_string()).unwrap_or_default();
+            if (uri.to_lowercase().contains(&text) || title.to_lowercase().contains(&text))
+                && seen.insert(uri.clone())
+            {
+                suggestions.push((title, uri));
+                if suggestions.len() >= MAX_SUGGESTIONS {
+                    return suggestions;
+                }
+            }
+        }
+    }
+    if let Some(history) = history {
+        for entry in history.search(&text, MAX_SUGGESTIONS) {
+            if seen.insert(entry.uri.clone()) {
+                suggestions.push((entry.title, entry.uri));
+                if suggestions.len() >= MAX_SUGGESTIONS {
+                    break;
+                }
+            }
+        }
+    }
+    suggestions
+}
+
+/// A popup list of suggestions below the location entry.
+pub struct Completion {
+    popover: Popover,
+    list_box: ListBox,
+    uris: Rc<RefCell<Vec<String>>>,
+}
+
+impl Completion {
+    pub fn new(entry: &gtk::Entry) -> Self {
+        let list_box = ListBox::new();
+        list_box.set_activate_on_single_click(true);
+        let popover = Popover::builder()
+            .child(&list_box)
+            .autohide(false)
+            .has_arrow(false)
+            .position(gtk::PositionType::Bottom)
+            .halign(Align::Start)
+            .build();
+        popover.set_parent(entry);
+
+        // not autohidden, so that typing goes on in the entry; hide when the
+        // focus leaves both the entry and the suggestions, e.g. to the page
+        let entry_focus = gtk::EventControllerFocus::new();
+        let popover_focus = gtk::EventControllerFocus::new();
+        let hide_unfocused = glib::clone!(
+            #[weak]
+            popover,
+            #[weak]
+            entry_focus,
+            #[weak]
+            popover_focus,
+            move |_controller: &gtk::EventControllerFocus| {
+                // on the way from one to the other, neither has it yet
+                glib::idle_add_local_once(glib::clone!(
+                    #[weak]
+                    popover,
+                    #[weak]
+                    entry_focus,
+                    #[weak]
+                    popover_focus,
+                    move || {
+                        if !entry_focus.contains_focus() && !popover_focus.contains_focus() {
+                            popover.popdown();
+                        }
+                    }
+                ));
+            }
+        );
+        entry_focus.connect_leave(hide_unfocused.clone());
+        popover_focus.connect_leave(hide_unfocused);
+        entry.add_controller(entry_focus);
+        popover.add_controller(popover_focus);
+        entry.connect_destroy(glib::clone!(
+            #[weak]
+            popover,
+            move |_entry| {
+                popover.unparent();
+            }
+        ));
+        Self {
+            popover,
+            list_box,
+            uris: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    pub fn update(&self, suggestions: Vec<(String, String)>) {
+        self.list_box.remove_all();
+        if suggestions.is_empty() {
+            self.popover.popdown();
+            return;
+        }
+        let mut uris = self.uris.borrow_mut();
+        uris.clear();
+        for (title, uri) in suggestions {
+            let vbox = gtk::Box::new(Orientation::Vertical, 0);
+            let title_label = Label::builder()
+                .label(&title)
+                .ellipsize(gtk::pango::EllipsizeMode::End)
+                .max_width_chars(64)
+                .xalign(0.0)
+                .build();
+            vbox.append(&title_label);
+            let uri_label = Label::builder()
+                .label(&uri)
+                .ellipsize(gtk::pango::EllipsizeMode::Middle)
+                .max_width_chars(64)
+                .xalign(0.0)
+                .css_classes(["dim-label"])
+                .build();
+            vbox.append(&uri_label);
+            self.list_box.append(&vbox);
+            uris.push(uri);
+        }
+        self.popover.popup();
+    }
+
+    pub fn hide(&self) {
+        self.popover.popdown();
+    }
+
+    /// Move focus to the first suggestion.
+    pub fn focus_first(&self) -> bool {
+        if !self.popover.is_visible() {
+            return false;
+        }
+        match self.list_box.row_at_index(0) {
+            Some(row) => row.grab_focus(),
+            None => false,
+        }
+    }
+
+    pub fn connect_selected<F: Fn(&str) + 'static>(&self, f: F) {
+        let uris = self.uris.clone();
+        self.list_box.connect_row_activated(glib::clone!(
+            #[weak(rename_to = popover)]
+            self.popover,
+            move |_list_box, row| {
+                popover.popdown();
+                let uri = uris.borrow().get(row.index() as usize).cloned();
+                if let Some(uri) = uri {
+                    f(&uri);
+                }
+            }
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search() -> Search {
+        let mut search = Search::default();
+        search.default = "https://search.example/?q={}".to_owned();
+        search
+            .keywords
+            .insert("w".to_owned(), "https://wiki.example/{}".to_owned());
+        search
+    }
+
+    #[test]
+    fn urls() {
+        let search = search();
+        assert_eq!(
+            resolve("https://example.com/a", &search),
+            "https://example.com/a"
+        );
+        assert_eq!(
+            resolve("  example.com/a  ", &search),
+            "https://example.com/a"
+        );
+        assert_eq!(resolve("file:///tmp/a.html", &search), "file:///tmp/a.html");
+        assert_eq!(resolve("about:blank", &search), "about:blank");
+        assert_eq!(
+            resolve("mailto:a@example.com", &search),
+            "mailto:a@example.com"
+        );
+        assert_eq!(resolve("localhost", &search), "http://localhost");
+        assert_eq!(
+            resolve("localhost:8080/x", &search),
+            "http://localhost:8080/x"
+        );
+    }
+
+    #[test]
+    fn searches() {
+        let search = search();
+        assert_eq!(resolve("rust", &search), "https://search.example/?q=rust");
+        // has a dot, but also spaces
+        assert_eq!(
+            resolve("what is example.com", &search),
+            "https://search.example/?q=what%20is%20example.com"
+        );
+        assert_eq!(resolve("a&b", &search), "https://search.example/?q=a%26b");
+        // "scheme:" without "//" isn't a URL
+        assert_eq!(
+            resolve("foo:bar", &search),
+            "https://search.example/?q=foo%3Abar"
+        );
+    }
+
+    #[test]
+    fn keywords() {
+        let search = search();
+        assert_eq!(
+            resolve("w  gtk 4 ", &search),
+            "https://wiki.example/gtk%204"
+        );
+        // unknown keywords are searched as they are
+        assert_eq!(
+            resolve("x gtk", &search),
+            "https://search.example/?q=x%20gtk"
+        );
+        // a keyword alone is not a search with it
+        assert_eq!(resolve("w", &search), "https://search.example/?q=w");
+    }
+}
@@ -2,58 +2,410 @@ use gtk4 as gtk;
 
 use gtk::glib::clone;
 use gtk::prelude::*;
-use gtk::{gdk, gio};
-use std::cell::RefCell;
+use gtk::{gdk, gio, glib};
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
 use gtk::Application;
 
+mod bookmarks;
+mod bookmarkspopover;
+mod closed;
+mod contentfilter;
+mod downloads;
+mod downloadspopover;
 mod favicontitle;
+mod headless;
+mod hints;
+mod history;
+mod historywindow;
+mod keybindings;
+mod launcher;
+mod location;
+mod options;
+mod palette;
+mod profile;
+mod remote;
+mod restore;
+mod rules;
+mod sessions;
 mod settings;
+mod state;
+mod usercontent;
 mod viewer;
 mod window;
+mod zoom;
 
 fn main() {
-    let settings = Rc::new(RefCell::new(settings::load_settings()));
+    let state = Rc::new(state::AppState::new(settings::load_settings()));
 
-    let app = Application::new(Some("org.u7fa9.wv"), gio::ApplicationFlags::HANDLES_OPEN);
+    // options of the invocation being handled, consulted by "open" and "activate"
+    let invocation = Rc::new(RefCell::new(options::Options::default()));
+    // windows of the last run are restored only once
+    let restored = Rc::new(Cell::new(false));
+    // set by headless runs, which finish after the command line is handled
+    let exit_status = Rc::new(Cell::new(0));
+
+    let app = Application::new(
+        Some("org.u7fa9.wv"),
+        gio::ApplicationFlags::HANDLES_OPEN | gio::ApplicationFlags::HANDLES_COMMAND_LINE,
+    );
     app.set_option_context_parameter_string(Some("[URL]"));
-    app.connect_startup(|_app| {
-        let display = gdk::Display::default().expect("can't get display");
-        let provider = gtk::CssProvider::new();
-        provider.load_from_string(include_str!("css/style.css"));
-        gtk::style_context_add_provider_for_display(
-            &display,
-            &provider,
-            gtk::STYLE_PROVIDER_PRIORITY_USER,
-        );
+    options::add_main_options(&app);
+    app.connect_startup(clone!(
+        #[strong]
+        state,
+        move |app| {
+            let display = gdk::Display::default().expect("can't get display");
+            let provider = gtk::CssProvider::new();
+            provider.load_from_string(include_str!("css/style.css"));
+            gtk::style_context_add_provider_for_display(
+                &display,
+                &provider,
+                gtk::STYLE_PROVIDER_PRIORITY_USER,
+            );
+
+            let reopen_closed_action =
+                gio::SimpleAction::new("reopen-closed", Some(glib::VariantTy::UINT32));
+            reopen_closed_action.connect_activate(clone!(
+                #[weak]
+                app,
+                #[strong]
+                state,
+                move |_action, parameter| {
+                    let index = parameter.and_then(|p| p.get::<u32>()).unwrap_or(0);
+                    if let Some(closed) = state.closed_windows.take(index as usize) {
+                        closed.state.open(&app, &state);
+                    }
+                }
+            ));
+            app.add_action(&reopen_closed_action);
+
+            remote::register(app, &state);
+            keybindings::apply(app, &state.settings.borrow().keybindings);
+
+            state.user_content.start();
+            glib::MainContext::default().spawn_local(clone!(
+                #[weak]
+                app,
+                #[strong]
+                state,
+                async move {
+                    state.content_filters.load().await;
+                    // windows opened while compiling
+                    state.content_filters.apply_all(&app);
+                }
+            ));
+
+            let settings = state.settings.borrow();
+            let session = &settings.session;
+            if session.restore && session.autosave_interval > 0 {
+                glib::timeout_add_seconds_local(
+                    session.autosave_interval,
+                    clone!(
+                        #[weak]
+                        app,
+                        #[upgrade_or]
+                        glib::ControlFlow::Break,
+                        move || {
+                            if !app.windows().is_empty() {
+                                restore::save(&app, None);
+                            }
+                            glib::ControlFlow::Continue
+                        }
+                    ),
+                );
+            }
+        }
+    ));
+    app.connect_window_added(clone!(
+        #[strong]
+        state,
+        move |app, win| {
+            win.connect_close_request(clone!(
+                #[strong]
+                state,
+                #[weak]
+                app,
+                #[upgrade_or]
+                glib::Propagation::Proceed,
+                move |win| {
+                    state.closed_windows.push(win);
+                    if state.settings.borrow().session.restore {
+                        // keep the last window to restore it next time, not
+                        // counting history, palette or shortcuts windows
+                        let browser_windows = app
+                            .windows()
+                            .iter()
+                            .filter(|win| win.child().and_downcast::<viewer::Viewer>().is_some())
+                            .count();
+                        if browser_windows > 1 {
+                            restore::save(&app, Some(win));
+                        } else {
+                            restore::save(&app, None);
+                        }
+                    }
+                    glib::Propagation::Proceed
+                }
+            ));
+        }
+    ));
+    app.connect_handle_local_options(|app, options| {
+        // output of headless runs is written by this process, and remote
+        // commands are sent by it to the running one
+        if options.contains("new-instance")
+            || options::is_headless(options)
+            || options.contains("remote")
+        {
+            app.set_flags(app.flags() | gio::ApplicationFlags::NON_UNIQUE);
+        }
+        -1
     });
+    app.connect_command_line(clone!(
+        #[strong]
+        state,
+        #[strong]
+        invocation,
+        #[strong]
+        exit_status,
+        move |app, cmdline| {
+            let options = options::Options::from_dict(&cmdline.options_dict());
+            if let Err(e) = options.check() {
+                cmdline.printerr_literal(&format!("{}\n", e));
+                return 1;
+            }
+            if options.profile(&state.settings.borrow()).is_none() {
+                cmdline.printerr_literal(&format!(
+                    "unknown profile: {}\n",
+                    options.profile.unwrap_or_default()
+                ));
+                return 1;
+            }
+
+            if let Some(command) = &options.remote {
+                let args: Vec<_> = cmdline.arguments().into_iter().skip(1).collect();
+                return remote::run_client(command, &args, cmdline);
+            }
+            if let (Some(script), Some(id)) = (&options.eval, options.window) {
+                let result = remote::eval_in_window(
+                    app,
+                    id as u32,
+                    script,
+                    // the caller waits for its output until `cmdline` is released
+                    clone!(
+                        #[strong]
+                        cmdline,
+                        #[strong]
+                        exit_status,
+                        move |result| match result {
+                            Ok(json) => cmdline.print_literal(&format!("{}\n", json)),
+                            Err(e) => {
+                                cmdline.printerr_literal(&format!("{}\n", e));
+                                set_failed(&cmdline, &exit_status);
+                            }
+                        }
+                    ),
+                );
+                if let Err(e) = result {
+                    cmdline.printerr_literal(&format!("{}\n", e));
+                    return 1;
+                }
+                return 0;
+            }
+
+            let args: Vec<_> = cmdline.arguments().into_iter().skip(1).collect();
+            if options.is_headless() {
+                return headless::start(app, &state, &options, cmdline, &args, exit_status.clone());
+            }
+            // "-" means "read the content from stdin"
+            let read_stdin = args.iter().any(|arg| arg == "-");
+            let files: Vec<gio::File> = args
+                .iter()
+                .filter(|arg| *arg != "-")
+                .map(|arg| cmdline.create_file_for_arg(arg))
+                .collect();
+
+            if let Some(content) = &options.content {
+                let win = new_window(app, &state, &options);
+                win.load_content(
+                    &glib::Bytes::from(content.as_bytes()),
+                    options.content_type(),
+                    options.base_uri.as_deref(),
+                );
+            }
+            if read_stdin {
+                match cmdline.stdin() {
+                    Some(stdin) => {
+                        let hold_guard = app.hold();
+                        glib::MainContext::default().spawn_local(clone!(
+                            #[strong]
+                            app,
+                            #[strong]
+                            state,
+                            #[strong]
+                            options,
+                            // keep the invocation alive until the content is read
+                            #[strong]
+                            cmdline,
+                            #[strong]
+                            exit_status,
+                            async move {
+                                let _hold_guard = hold_guard;
+                                match read_all(&stdin).await {
+                                    Ok(content) => {
+                                        let win = new_window(&app, &state, &options);
+                                        win.load_content(
+                                            &content,
+                                            options.content_type(),
+                                            options.base_uri.as_deref(),
+                                        );
+                                    }
+                                    Err(e) => {
+                                        cmdline.printerr_literal(&format!(
+                                            "failed to read stdin: {}\n",
+                                            e
+                                        ));
+                                        set_failed(&cmdline, &exit_status);
+                                    }
+                                }
+                            }
+                        ));
+                    }
+                    None => {
+                        cmdline.printerr_literal("stdin is not available\n");
+                        return 1;
+                    }
+                }
+            }
+
+            *invocation.borrow_mut() = options;
+            if !files.is_empty() {
+                app.open(&files, "");
+            } else if !read_stdin && invocation.borrow().content.is_none() {
+                app.activate();
+            }
+            *invocation.borrow_mut() = options::Options::default();
+            0
+        }
+    ));
     app.connect_open(clone!(
         #[strong]
-        settings,
+        state,
+        #[strong]
+        invocation,
+        #[strong]
+        restored,
         move |app, files, _hints| {
-            for f in files {
-                let win = window::Window::new(&app, settings.clone(), None);
-                win.widget.present();
-                win.load_uri(&f.uri());
+            restored.set(true);
+            let options = invocation.borrow();
+            let (reuse, single) = {
+                let settings = state.settings.borrow();
+                (
+                    options.reuse_window(&settings),
+                    options.single_window(&settings),
+                )
+            };
+            let uris: Vec<String> = files.iter().map(|f| f.uri().to_string()).collect();
+            if !single {
+                for uri in &uris {
+                    let win = new_window(app, &state, &options);
+                    win.load_uri(uri);
+                }
+                return;
+            }
+            let profile = options
+                .profile(&state.settings.borrow())
+                .unwrap_or_else(profile::Profile::private);
+            // the most recently focused window of the same profile
+            let reused = app
+                .windows()
+                .into_iter()
+                .filter(|_| reuse)
+                .filter_map(|win| win.child().and_downcast::<viewer::Viewer>())
+                .find(|viewer| {
+                    let other = viewer.profile();
+                    other.name == profile.name && other.isolated == profile.isolated
+                });
+            match reused {
+                Some(viewer) => {
+                    if let Some(win) = viewer.root().and_downcast::<gtk::Window>() {
+                        win.present();
+                    }
+                    viewer.load_uris(uris);
+                }
+                None => new_window(app, &state, &options).load_uris(uris),
             }
         }
     ));
     app.connect_activate(clone!(
         #[strong]
-        settings,
+        state,
+        #[strong]
+        invocation,
+        #[strong]
+        restored,
         move |app| {
-            let win = window::Window::new(&app, settings.clone(), None);
-            win.widget.present();
+            if !restored.replace(true) && state.settings.borrow().session.restore {
+                let windows = restore::load();
+                for window_state in &windows {
+                    window_state.open(app, &state);
+                }
+                if !windows.is_empty() {
+                    return;
+                }
+            }
+            let win = new_window(app, &state, &invocation.borrow());
             win.load_uri("about:blank");
         }
     ));
     app.connect_shutdown(clone!(
         #[strong]
-        settings,
+        state,
         move |_app| {
-            settings::save_settings(&settings.borrow());
+            state.history.save_all();
+            settings::save_settings(&state.settings.borrow());
         }
     ));
-    app.run();
+    let status = app.run().value().max(exit_status.get());
+    if status != 0 {
+        std::process::exit(status);
+    }
+}
+
+/// Make the invocation of `cmdline` fail after it was handled. A local one
+/// ignores `set_exit_status()` once the main loop runs.
+fn set_failed(cmdline: &gio::ApplicationCommandLine, exit_status: &Cell<i32>) {
+    if cmdline.is_remote() {
+        cmdline.set_exit_status(1);
+    } else {
+        exit_status.set(1);
+    }
+}
+
+fn new_window(
+    app: &Application,
+    state: &Rc<state::AppState>,
+    options: &options::Options,
+) -> window::Window {
+    let profile = options
+        .profile(&state.settings.borrow())
+        .unwrap_or_else(profile::Profile::private);
+    let win = window::Window::new(app, state.clone(), profile, None);
+    win.apply_options(options);
+    win.widget.present();
+    win
+}
+
+async fn read_all(stream: &gio::InputStream) -> Result<glib::Bytes, glib::Error> {
+    let mut content = Vec::new();
+    loop {
+        let bytes = stream
+            .read_bytes_future(64 * 1024, glib::Priority::DEFAULT)
+            .await?;
+        if bytes.is_empty() {
+            break;
+        }
+        content.extend_from_slice(&bytes);
+    }
+    Ok(glib::Bytes::from_owned(content))
 }
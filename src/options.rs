@@ -0,0 +1,405 @@
+use gtk4 as gtk;
+
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::Application;
+
+use crate::profile::Profile;
+use crate::settings::Settings;
+
+/// How content given on stdin or with `--content` is displayed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentType {
+    Html,
+    Markdown,
+    Text,
+}
+
+impl ContentType {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "html" => Some(ContentType::Html),
+            "markdown" | "md" => Some(ContentType::Markdown),
+            "text" | "plain" => Some(ContentType::Text),
+            _ => None,
+        }
+    }
+}
+
+/// What `--dump` prints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DumpFormat {
+    Text,
+    Html,
+    Markdown,
+}
+
+impl DumpFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(DumpFormat::Text),
+            "html" => Some(DumpFormat::Html),
+            "markdown" | "md" => Some(DumpFormat::Markdown),
+            _ => None,
+        }
+    }
+}
+
+/// Options given to a single invocation of `wv`.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    pub profile: Option<String>,
+    pub isolated: bool,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub fullscreen: bool,
+    pub user_agent: Option<String>,
+    pub zoom: Option<f64>,
+    pub no_javascript: bool,
+    pub content: Option<String>,
+    content_type: Option<String>,
+    pub base_uri: Option<String>,
+    pub screenshot: Option<String>,
+    pub pdf: Option<String>,
+    pub full_page: bool,
+    pub delay: Option<f64>,
+    pub batch: Option<String>,
+    dump: Option<String>,
+    pub wait_for: Option<String>,
+    pub eval: Option<String>,
+    pub window: Option<i32>,
+    pub remote: Option<String>,
+    reuse_window: bool,
+    new_window: bool,
+    single_window: bool,
+}
+
+pub fn add_main_options(app: &Application) {
+    app.add_main_option(
+        "profile",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Use a profile defined in settings.toml (default: private)",
+        Some("NAME"),
+    );
+    app.add_main_option(
+        "isolated",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Don't share the network session with other windows",
+        None,
+    );
+    app.add_main_option(
+        "width",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::Int,
+        "Width of new windows",
+        Some("WIDTH"),
+    );
+    app.add_main_option(
+        "height",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::Int,
+        "Height of new windows",
+        Some("HEIGHT"),
+    );
+    app.add_main_option(
+        "fullscreen",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Open windows in fullscreen",
+        None,
+    );
+    app.add_main_option(
+        "user-agent",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "User-Agent sent by new windows",
+        Some("USER_AGENT"),
+    );
+    app.add_main_option(
+        "zoom",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::Double,
+        "Zoom level of new windows (1.0 = 100%)",
+        Some("LEVEL"),
+    );
+    app.add_main_option(
+        "no-javascript",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Disable JavaScript in new windows",
+        None,
+    );
+    app.add_main_option(
+        "new-instance",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Don't pass the request to a running wv",
+        None,
+    );
+    app.add_main_option(
+        "reuse-window",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Load URLs in the most recently focused window",
+        None,
+    );
+    app.add_main_option(
+        "new-window",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Load URLs in new windows",
+        None,
+    );
+    app.add_main_option(
+        "single-window",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Load all URLs in one window, as its back/forward list",
+        None,
+    );
+    app.add_main_option(
+        "content",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Display CONTENT (give \"-\" as URL to read it from stdin)",
+        Some("CONTENT"),
+    );
+    app.add_main_option(
+        "type",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Type of the content: html (default), markdown or text",
+        Some("TYPE"),
+    );
+    app.add_main_option(
+        "base-uri",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Base URI to resolve relative links in the content",
+        Some("URI"),
+    );
+    app.add_main_option(
+        "screenshot",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Save a PNG image of the page to FILE and exit",
+        Some("FILE"),
+    );
+    app.add_main_option(
+        "pdf",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Print the page to a PDF FILE and exit",
+        Some("FILE"),
+    );
+    app.add_main_option(
+        "full-page",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Take the screenshot of the whole document, not only the visible area",
+        None,
+    );
+    app.add_main_option(
+        "delay",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::Double,
+        "Seconds to wait after the page is loaded, before taking the output",
+        Some("SECONDS"),
+    );
+    app.add_main_option(
+        "batch",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Read lines of \"URL [OUTPUT]\" from LIST (\"-\" for stdin)",
+        Some("LIST"),
+    );
+    app.add_main_option(
+        "dump",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Print the rendered page as text, html or markdown and exit",
+        Some("FORMAT"),
+    );
+    app.add_main_option(
+        "wait-for",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Wait until an element matching SELECTOR appears, after the page is loaded",
+        Some("SELECTOR"),
+    );
+    app.add_main_option(
+        "eval",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Print the value of SCRIPT run in the page as JSON and exit",
+        Some("SCRIPT"),
+    );
+    app.add_main_option(
+        "window",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::Int,
+        "Run --eval in window ID of the running wv (0 for the active one)",
+        Some("ID"),
+    );
+    app.add_main_option(
+        "remote",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Send COMMAND (list, open, navigate, reload, close or focus) to the running wv",
+        Some("COMMAND"),
+    );
+}
+
+/// Options that make `wv` exit after writing its output, instead of opening
+/// windows.
+pub fn is_headless(dict: &glib::VariantDict) -> bool {
+    ["screenshot", "pdf", "dump"]
+        .iter()
+        .any(|name| dict.contains(name))
+        || (dict.contains("eval") && !dict.contains("window"))
+}
+
+impl Options {
+    pub fn from_dict(dict: &glib::VariantDict) -> Self {
+        Self {
+            profile: dict.lookup::<String>("profile").ok().flatten(),
+            isolated: dict.contains("isolated"),
+            width: dict.lookup::<i32>("width").ok().flatten(),
+            height: dict.lookup::<i32>("height").ok().flatten(),
+            fullscreen: dict.contains("fullscreen"),
+            user_agent: dict.lookup::<String>("user-agent").ok().flatten(),
+            zoom: dict.lookup::<f64>("zoom").ok().flatten(),
+            no_javascript: dict.contains("no-javascript"),
+            content: dict.lookup::<String>("content").ok().flatten(),
+            content_type: dict.lookup::<String>("type").ok().flatten(),
+            base_uri: dict.lookup::<String>("base-uri").ok().flatten(),
+            screenshot: dict.lookup::<String>("screenshot").ok().flatten(),
+            pdf: dict.lookup::<String>("pdf").ok().flatten(),
+            full_page: dict.contains("full-page"),
+            delay: dict.lookup::<f64>("delay").ok().flatten(),
+            batch: dict.lookup::<String>("batch").ok().flatten(),
+            dump: dict.lookup::<String>("dump").ok().flatten(),
+            wait_for: dict.lookup::<String>("wait-for").ok().flatten(),
+            eval: dict.lookup::<String>("eval").ok().flatten(),
+            window: dict.lookup::<i32>("window").ok().flatten(),
+            remote: dict.lookup::<String>("remote").ok().flatten(),
+            reuse_window: dict.contains("reuse-window"),
+            new_window: dict.contains("new-window"),
+            single_window: dict.contains("single-window"),
+        }
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        if let Some(width) = self.width.filter(|w| *w <= 0) {
+            return Err(format!("invalid width: {}", width));
+        }
+        if let Some(height) = self.height.filter(|h| *h <= 0) {
+            return Err(format!("invalid height: {}", height));
+        }
+        if let Some(zoom) = self.zoom.filter(|z| *z <= 0.0) {
+            return Err(format!("invalid zoom level: {}", zoom));
+        }
+        let outputs = [&self.screenshot, &self.pdf, &self.dump, &self.eval];
+        if outputs.iter().filter(|output| output.is_some()).count() > 1 {
+            return Err(
+                "only one of --screenshot, --pdf, --dump and --eval can be used".to_owned(),
+            );
+        }
+        if self.remote.is_some() && (self.is_headless() || self.window.is_some()) {
+            return Err("--remote can't be used with other output options".to_owned());
+        }
+        if self.reuse_window && self.new_window {
+            return Err("only one of --reuse-window and --new-window can be used".to_owned());
+        }
+        if let Some(id) = self.window.filter(|id| *id < 0) {
+            return Err(format!("invalid window id: {}", id));
+        }
+        if self.window.is_some() && self.eval.is_none() {
+            return Err("--window needs --eval".to_owned());
+        }
+        if let Some(dump) = &self.dump {
+            if DumpFormat::from_name(dump).is_none() {
+                return Err(format!("unknown dump format: {}", dump));
+            }
+        }
+        if let Some(delay) = self.delay.filter(|d| *d < 0.0) {
+            return Err(format!("invalid delay: {}", delay));
+        }
+        if self.batch.is_some() && self.screenshot.is_none() && self.pdf.is_none() {
+            return Err("--batch needs --screenshot or --pdf".to_owned());
+        }
+        if let Some(content_type) = &self.content_type {
+            if ContentType::from_name(content_type).is_none() {
+                return Err(format!("unknown content type: {}", content_type));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether to write output of pages instead of opening windows.
+    pub fn is_headless(&self) -> bool {
+        self.screenshot.is_some()
+            || self.pdf.is_some()
+            || self.dump.is_some()
+            || (self.eval.is_some() && self.window.is_none())
+    }
+
+    /// Whether to load URLs in the most recently focused window of the
+    /// profile. A window with a session of its own is never reused.
+    pub fn reuse_window(&self, settings: &Settings) -> bool {
+        let reuse = (settings.open.reuse_window || self.reuse_window) && !self.new_window;
+        reuse && !self.isolated
+    }
+
+    /// Whether to load several URLs in one window.
+    pub fn single_window(&self, settings: &Settings) -> bool {
+        settings.open.single_window || self.single_window || self.reuse_window(settings)
+    }
+
+    pub fn dump_format(&self) -> Option<DumpFormat> {
+        self.dump.as_deref().and_then(DumpFormat::from_name)
+    }
+
+    pub fn content_type(&self) -> ContentType {
+        self.content_type
+            .as_deref()
+            .and_then(ContentType::from_name)
+            .unwrap_or(ContentType::Html)
+    }
+
+    pub fn profile(&self, settings: &Settings) -> Option<Profile> {
+        let mut profile = match &self.profile {
+            Some(name) => Profile::lookup(settings, name)?,
+            None => Profile::private(),
+        };
+        profile.isolated = self.isolated;
+        Some(profile)
+    }
+}
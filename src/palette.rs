@@ -0,0 +1,374 @@
+use gtk4 as gtk;
+
+use gtk::prelude::*;
+use gtk::{gdk, gio, glib};
+use gtk::{
+    Application, ApplicationWindow, Label, ListBox, Orientation, ScrolledWindow, SearchEntry,
+};
+use std::cell::RefCell;
+use std::rc::Rc;
+use webkit6::prelude::*;
+use webkit6::WebView;
+
+use crate::keybindings;
+use crate::launcher;
+use crate::settings::Settings;
+use crate::viewer::Viewer;
+
+/// Something to do, picked from the palette.
+struct Command {
+    title: String,
+    subtitle: String,
+    accel: String,
+    run: Rc<dyn Fn()>,
+}
+
+/// Show a palette of actions of `window` and `app`, re-open targets and
+/// other windows, searchable by fuzzy matching.
+pub fn present(
+    window: &ApplicationWindow,
+    app: &Application,
+    settings: &Rc<RefCell<Settings>>,
+    webview: &WebView,
+) {
+    let commands = Rc::new(collect_commands(window, app, settings, webview));
+
+    let win = gtk::Window::builder()
+        .transient_for(window)
+        .modal(true)
+        .decorated(false)
+        .default_width(560)
+        .default_height(400)
+        .build();
+    let vbox = gtk::Box::new(Orientation::Vertical, 0);
+    let search_entry = SearchEntry::builder()
+        .placeholder_text("Run a command")
+        .margin_top(6)
+        .margin_bottom(6)
+        .margin_start(6)
+        .margin_end(6)
+        .build();
+    vbox.append(&search_entry);
+    let list_box = ListBox::new();
+    list_box.set_activate_on_single_click(true);
+    let scrolled = ScrolledWindow::builder()
+        .child(&list_box)
+        .vexpand(true)
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .build();
+    vbox.append(&scrolled);
+    win.set_child(Some(&vbox));
+
+    // indices into `commands` of the rows shown
+    let shown: Rc<RefCell<Vec<usize>>> = Rc::new(RefCell::new(Vec::new()));
+    let populate = Rc::new(glib::clone!(
+        #[weak]
+        list_box,
+        #[weak]
+        search_entry,
+        #[strong]
+        commands,
+        #[strong]
+        shown,
+        move || {
+            list_box.remove_all();
+            let text = search_entry.text();
+            let mut scored: Vec<(i32, usize)> = commands
+                .iter()
+                .enumerate()
+                .filter_map(|(i, command)| {
+                    let score = fuzzy_score(&text, &command.title).or_else(|| {
+                        // matches in subtitles come after those in titles
+                        fuzzy_score(&text, &command.subtitle).map(|score| score - 1000)
+                    })?;
+                    Some((score, i))
+                })
+                .collect();
+            // stable, so equal ones keep the order of collect_commands()
+            scored.sort_by_key(|(score, _)| -score);
+            let mut shown = shown.borrow_mut();
+            shown.clear();
+            for (_, i) in scored {
+                list_box.append(&build_row(&commands[i]));
+                shown.push(i);
+            }
+            list_box.select_row(list_box.row_at_index(0).as_ref());
+        }
+    ));
+    populate();
+
+    search_entry.connect_search_changed(glib::clone!(
+        #[strong]
+        populate,
+        move |_entry| {
+            populate();
+        }
+    ));
+    search_entry.connect_activate(glib::clone!(
+        #[weak]
+        list_box,
+        move |_entry| {
+            if let Some(row) = list_box.selected_row() {
+                row.activate();
+            }
+        }
+    ));
+    list_box.connect_row_activated(glib::clone!(
+        #[weak]
+        win,
+        #[strong]
+        commands,
+        #[strong]
+        shown,
+        move |_list_box, row| {
+            let run = shown
+                .borrow()
+                .get(row.index() as usize)
+                .map(|i| commands[*i].run.clone());
+            win.close();
+            if let Some(run) = run {
+                run();
+            }
+        }
+    ));
+
+    let key_controller = gtk::EventControllerKey::new();
+    key_controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+    key_controller.connect_key_pressed(glib::clone!(
+        #[weak]
+        win,
+        #[weak]
+        list_box,
+        #[weak]
+        scrolled,
+        #[upgrade_or]
+        glib::Propagation::Proceed,
+        move |_controller, key, _keycode, _state| {
+            let step = match key {
+                gdk::Key::Escape => {
+                    win.close();
+                    return glib::Propagation::Stop;
+                }
+                gdk::Key::Down => 1,
+                gdk::Key::Up => -1,
+                _ => return glib::Propagation::Proceed,
+            };
+            let index = list_box.selected_row().map_or(-1, |row| row.index()) + step;
+            // keep the focus in the search entry
+            if let Some(row) = list_box.row_at_index(index) {
+                list_box.select_row(Some(&row));
+                if let Some(viewport) = scrolled.child().and_downcast::<gtk::Viewport>() {
+                    viewport.scroll_to(&row, None);
+                }
+            }
+            glib::Propagation::Stop
+        }
+    ));
+    win.add_controller(key_controller);
+    // close when focus moves to another window
+    win.connect_is_active_notify(|win| {
+        if !win.is_active() {
+            win.close();
+        }
+    });
+
+    win.present();
+    search_entry.grab_focus();
+}
+
+fn collect_commands(
+    window: &ApplicationWindow,
+    app: &Application,
+    settings: &Rc<RefCell<Settings>>,
+    webview: &WebView,
+) -> Vec<Command> {
+    let mut commands = Vec::new();
+
+    let groups: [(&str, gio::ActionGroup, gio::ActionMap); 2] = [
+        ("win", window.clone().upcast(), window.clone().upcast()),
+        ("app", app.clone().upcast(), app.clone().upcast()),
+    ];
+    for (prefix, group, map) in groups.iter() {
+        let mut names = group.list_actions();
+        names.sort();
+        for name in names {
+            let full_name = format!("{}.{}", prefix, name);
+            if full_name == "win.palette" {
+                continue;
+            }
+            let action = match map.lookup_action(&name) {
+                Some(action) if action.is_enabled() => action,
+                _ => continue,
+            };
+            let known = keybindings::ACTIONS
+                .iter()
+                .find(|known| known.name == full_name);
+            let parameter = match (action.parameter_type(), known.and_then(|k| k.target)) {
+                (None, _) => None,
+                (Some(ty), Some(target)) => match glib::Variant::parse(Some(&*ty), target) {
+                    Ok(parameter) => Some(parameter),
+                    Err(_) => continue,
+                },
+                // nothing to pass
+                (Some(_), None) => continue,
+            };
+            let detailed_name = known.map_or_else(|| full_name.clone(), |k| k.detailed_name());
+            let title = known.map_or_else(|| humanize(&name), |k| k.description.to_owned());
+            let group = group.clone();
+            commands.push(Command {
+                title,
+                subtitle: full_name,
+                accel: accel_label(app, &detailed_name),
+                run: Rc::new(move || group.activate_action(&name, parameter.as_ref())),
+            });
+        }
+    }
+
+    for target in launcher::targets(&settings.borrow().reopen) {
+        let webview = webview.clone();
+        commands.push(Command {
+            title: format!("Re-open with {}", target.label()),
+            subtitle: "re-open page with ...".to_owned(),
+            accel: String::new(),
+            run: Rc::new(move || {
+                if let Some(uri) = webview.uri() {
+                    let title = webview.title().unwrap_or_default();
+                    target.launch(&webview, &uri, &title);
+                }
+            }),
+        });
+    }
+
+    for other in app.windows() {
+        if other == *window.upcast_ref::<gtk::Window>() {
+            continue;
+        }
+        // the window title is always "Web View"
+        let webview = match other.child().and_downcast::<Viewer>() {
+            Some(viewer) => viewer.webview(),
+            None => continue,
+        };
+        let uri = webview.uri().map(|uri| uri.to_string()).unwrap_or_default();
+        let title = webview.title().map(|t| t.to_string()).unwrap_or_default();
+        commands.push(Command {
+            title: format!("Switch to {}", if title.is_empty() { &uri } else { &title }),
+            subtitle: uri,
+            accel: String::new(),
+            run: Rc::new(move || other.present()),
+        });
+    }
+
+    commands
+}
+
+fn build_row(command: &Command) -> gtk::Box {
+    let hbox = gtk::Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(12)
+        .margin_top(3)
+        .margin_bottom(3)
+        .margin_start(6)
+        .margin_end(6)
+        .build();
+    let vbox = gtk::Box::new(Orientation::Vertical, 0);
+    vbox.set_hexpand(true);
+    vbox.append(
+        &Label::builder()
+            .label(&command.title)
+            .ellipsize(gtk::pango::EllipsizeMode::End)
+            .xalign(0.0)
+            .build(),
+    );
+    vbox.append(
+        &Label::builder()
+            .label(&command.subtitle)
+            .ellipsize(gtk::pango::EllipsizeMode::Middle)
+            .xalign(0.0)
+            .css_classes(["dim-label"])
+            .build(),
+    );
+    hbox.append(&vbox);
+    if !command.accel.is_empty() {
+        hbox.append(
+            &Label::builder()
+                .label(&command.accel)
+                .css_classes(["dim-label"])
+                .build(),
+        );
+    }
+    hbox
+}
+
+// the first accelerator of the action, for display
+fn accel_label(app: &Application, detailed_name: &str) -> String {
+    app.accels_for_action(detailed_name)
+        .first()
+        .and_then(|accel| gtk::accelerator_parse(accel))
+        .map(|(key, modifiers)| gtk::accelerator_get_label(key, modifiers).to_string())
+        .unwrap_or_default()
+}
+
+// "zoom-text-only" -> "Zoom text only"
+fn humanize(name: &str) -> String {
+    let words = name.replace('-', " ");
+    let mut chars = words.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => words,
+    }
+}
+
+/// Score of `pattern` found in `text` as a subsequence, ignoring case, or
+/// None if not found. Matches at word starts and runs of adjacent matches
+/// score higher, gaps lower.
+fn fuzzy_score(pattern: &str, text: &str) -> Option<i32> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for c in pattern
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+    {
+        let found = position + text[position..].iter().position(|t| *t == c)?;
+        score += 1;
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 8;
+        }
+        match previous {
+            Some(previous) if previous + 1 == found => score += 5,
+            Some(previous) => score -= (found - previous - 1).min(10) as i32,
+            None => score -= found.min(10) as i32,
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy() {
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert_eq!(fuzzy_score("xyz", "Zoom in"), None);
+        // in order only
+        assert_eq!(fuzzy_score("ni", "in"), None);
+        assert!(fuzzy_score("ZOOM", "zoom in").is_some());
+        assert!(fuzzy_score("z i", "Zoom in").is_some());
+    }
+
+    #[test]
+    fn fuzzy_ranking() {
+        let score = |pattern, text| fuzzy_score(pattern, text).unwrap();
+        // word starts over the middle of words
+        assert!(score("zi", "Zoom in") > score("zi", "Zoom zoomed bizarre"));
+        // adjacent over scattered
+        assert!(score("find", "Find in page") > score("find", "Follow link in new window"));
+        // earlier over later
+        assert!(score("r", "Reload") > score("r", "Stop or reload"));
+    }
+}
@@ -0,0 +1,112 @@
+use std::path::{Path, PathBuf};
+use webkit6::{CookieAcceptPolicy, CookiePersistentStorage, NetworkSession};
+
+use crate::settings::{self, Settings};
+
+/// Name of the built-in profile which never writes anything to disk.
+pub const PRIVATE_PROFILE: &'static str = "private";
+
+const COOKIES_FILE_NAME: &'static str = "cookies.sqlite";
+
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    /// Use a session of its own instead of the one shared in the profile.
+    pub isolated: bool,
+    // None for the private (ephemeral) profile
+    dirs: Option<(PathBuf, PathBuf)>,
+}
+
+impl Profile {
+    pub fn private() -> Self {
+        Self {
+            name: PRIVATE_PROFILE.to_owned(),
+            isolated: false,
+            dirs: None,
+        }
+    }
+
+    /// Look up a profile defined in settings.
+    ///
+    /// Returns None if there is no such profile.
+    pub fn lookup(settings: &Settings, name: &str) -> Option<Self> {
+        if name == PRIVATE_PROFILE {
+            return Some(Self::private());
+        }
+        let profile = settings.profiles.get(name)?;
+
+        let data_dir = match &profile.data_dir {
+            Some(dir) => dir.clone(),
+            None => {
+                let mut dir = settings::get_app_config_dir()?;
+                dir.push("profiles");
+                dir.push(name);
+                dir
+            }
+        };
+        let cache_dir = match &profile.cache_dir {
+            Some(dir) => dir.clone(),
+            None => {
+                let mut dir = settings::get_app_cache_dir()?;
+                dir.push("profiles");
+                dir.push(name);
+                dir
+            }
+        };
+        Some(Self {
+            name: name.to_owned(),
+            isolated: false,
+            dirs: Some((data_dir, cache_dir)),
+        })
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.dirs.is_none()
+    }
+
+    /// Directory to keep data of wv itself, such as history.
+    ///
+    /// None if nothing should be written to disk for this profile.
+    pub fn data_dir(&self) -> Option<&Path> {
+        match &self.dirs {
+            Some((data_dir, _)) if !self.isolated => Some(data_dir),
+            _ => None,
+        }
+    }
+
+    pub fn create_network_session(&self) -> NetworkSession {
+        let network_session = match &self.dirs {
+            // directories of a persistent profile can't be shared with another session,
+            // so isolated windows are always ephemeral
+            Some((data_dir, cache_dir)) if !self.isolated => {
+                for dir in [data_dir, cache_dir] {
+                    if let Err(e) = std::fs::create_dir_all(dir) {
+                        eprintln!("failed to create {}: {}", dir.display(), e);
+                    }
+                }
+                let network_session = NetworkSession::new(
+                    Some(data_dir.to_string_lossy().as_ref()),
+                    Some(cache_dir.to_string_lossy().as_ref()),
+                );
+                network_session
+                    .cookie_manager()
+                    .unwrap()
+                    .set_persistent_storage(
+                        &data_dir.join(COOKIES_FILE_NAME).to_string_lossy(),
+                        CookiePersistentStorage::Sqlite,
+                    );
+                network_session
+            }
+            _ => NetworkSession::new_ephemeral(),
+        };
+        network_session
+            .cookie_manager()
+            .unwrap()
+            .set_accept_policy(CookieAcceptPolicy::NoThirdParty);
+        network_session.set_itp_enabled(true);
+        if let Some(website_data_manager) = network_session.website_data_manager() {
+            website_data_manager.set_favicons_enabled(true);
+        }
+        network_session
+    }
+}
@@ -0,0 +1,281 @@
+use gtk4 as gtk;
+
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use gtk::{Application, ApplicationWindow};
+use std::ffi::OsString;
+use std::rc::Rc;
+
+use crate::headless;
+use crate::profile::Profile;
+use crate::state::AppState;
+use crate::viewer::Viewer;
+use crate::window;
+
+const BUS_NAME: &str = "org.u7fa9.wv";
+const OBJECT_PATH: &str = "/org/u7fa9/wv";
+const INTERFACE_NAME: &str = "org.u7fa9.wv.Remote";
+const NO_SUCH_WINDOW_ERROR: &str = "org.u7fa9.wv.Error.NoSuchWindow";
+const SCRIPT_ERROR: &str = "org.u7fa9.wv.Error.Script";
+const UNKNOWN_METHOD_ERROR: &str = "org.freedesktop.DBus.Error.UnknownMethod";
+
+/// The interface exported by a running `wv`. Window ids are those of
+/// GtkApplicationWindow; 0 means the most recently focused window.
+const INTROSPECTION_XML: &str = r#"
+<node>
+  <interface name="org.u7fa9.wv.Remote">
+    <method name="ListWindows">
+      <arg type="a(uss)" name="windows" direction="out"/>
+    </method>
+    <method name="Navigate">
+      <arg type="u" name="id" direction="in"/>
+      <arg type="s" name="uri" direction="in"/>
+    </method>
+    <method name="Reload">
+      <arg type="u" name="id" direction="in"/>
+    </method>
+    <method name="Close">
+      <arg type="u" name="id" direction="in"/>
+    </method>
+    <method name="Focus">
+      <arg type="u" name="id" direction="in"/>
+    </method>
+    <method name="Open">
+      <arg type="s" name="uri" direction="in"/>
+      <arg type="u" name="id" direction="out"/>
+    </method>
+    <method name="Eval">
+      <arg type="u" name="id" direction="in"/>
+      <arg type="s" name="script" direction="in"/>
+      <arg type="s" name="json" direction="out"/>
+    </method>
+  </interface>
+</node>
+"#;
+
+/// The browser window with `id`, or the most recently focused one for 0.
+pub fn lookup_window(app: &Application, id: u32) -> Option<ApplicationWindow> {
+    if id == 0 {
+        app.windows()
+            .into_iter()
+            .filter_map(|win| win.downcast::<ApplicationWindow>().ok())
+            .find(|win| win.child().and_downcast::<Viewer>().is_some())
+    } else {
+        app.window_by_id(id)
+            .and_downcast::<ApplicationWindow>()
+            .filter(|win| win.child().and_downcast::<Viewer>().is_some())
+    }
+}
+
+fn viewer_of(win: &ApplicationWindow) -> Viewer {
+    win.child().and_downcast::<Viewer>().unwrap()
+}
+
+/// Run `script` in the window with `id`, or the active one for 0, calling
+/// `report` with its value as JSON or the exception.
+pub fn eval_in_window<F: FnOnce(Result<String, String>) + 'static>(
+    app: &Application,
+    id: u32,
+    script: &str,
+    report: F,
+) -> Result<(), String> {
+    let webview = match lookup_window(app, id) {
+        Some(win) => viewer_of(&win).webview(),
+        None => return Err(format!("no such window: {}", id)),
+    };
+    let script = script.to_owned();
+    glib::MainContext::default().spawn_local(async move {
+        report(headless::evaluate(&webview, &script).await);
+    });
+    Ok(())
+}
+
+/// Export the remote-control interface of `app` on its bus connection.
+pub fn register(app: &Application, state: &Rc<AppState>) {
+    let connection = match app.dbus_connection() {
+        Some(connection) => connection,
+        None => return,
+    };
+    let interface = gio::DBusNodeInfo::for_xml(INTROSPECTION_XML)
+        .ok()
+        .and_then(|node| node.lookup_interface(INTERFACE_NAME))
+        .expect("invalid introspection data");
+    let app = app.downgrade();
+    let state = state.clone();
+    let result = connection
+        .register_object(OBJECT_PATH, &interface)
+        .method_call(
+            move |_connection, _sender, _path, _interface, method, parameters, invocation| {
+                let app = match app.upgrade() {
+                    Some(app) => app,
+                    None => return,
+                };
+                // replied when the script is done
+                if method == "Eval" {
+                    let (id, script) = parameters.get::<(u32, String)>().unwrap();
+                    let webview = match lookup_window(&app, id) {
+                        Some(win) => viewer_of(&win).webview(),
+                        None => {
+                            let message = format!("no such window: {}", id);
+                            invocation.return_dbus_error(NO_SUCH_WINDOW_ERROR, &message);
+                            return;
+                        }
+                    };
+                    glib::MainContext::default().spawn_local(async move {
+                        match headless::evaluate(&webview, &script).await {
+                            Ok(json) => invocation.return_value(Some(&(json,).to_variant())),
+                            Err(e) => invocation.return_dbus_error(SCRIPT_ERROR, &e),
+                        }
+                    });
+                    return;
+                }
+                match handle_call(&app, &state, method, &parameters) {
+                    Ok(value) => invocation.return_value(value.as_ref()),
+                    Err((name, message)) => invocation.return_dbus_error(name, &message),
+                }
+            },
+        )
+        .build();
+    if let Err(e) = result {
+        eprintln!("failed to export {}: {}", INTERFACE_NAME, e);
+    }
+}
+
+// errors are D-Bus error names and messages
+fn handle_call(
+    app: &Application,
+    state: &Rc<AppState>,
+    method: &str,
+    parameters: &glib::Variant,
+) -> Result<Option<glib::Variant>, (&'static str, String)> {
+    let window = |id: u32| {
+        lookup_window(app, id)
+            .ok_or_else(|| (NO_SUCH_WINDOW_ERROR, format!("no such window: {}", id)))
+    };
+    match method {
+        "ListWindows" => {
+            let windows: Vec<(u32, String, String)> = app
+                .windows()
+                .into_iter()
+                .filter_map(|win| win.downcast::<ApplicationWindow>().ok())
+                .filter_map(|win| {
+                    let viewer = win.child().and_downcast::<Viewer>()?;
+                    let webview = viewer.webview();
+                    let uri = webview.uri().unwrap_or_default().to_string();
+                    // the window title is always "Web View"
+                    let title = webview.title().unwrap_or_default().to_string();
+                    Some((win.id(), uri, title))
+                })
+                .collect();
+            Ok(Some((windows,).to_variant()))
+        }
+        "Navigate" => {
+            let (id, uri) = parameters.get::<(u32, String)>().unwrap();
+            viewer_of(&window(id)?).webview().load_uri(&uri);
+            Ok(None)
+        }
+        "Reload" => {
+            let (id,) = parameters.get::<(u32,)>().unwrap();
+            viewer_of(&window(id)?).webview().reload();
+            Ok(None)
+        }
+        "Close" => {
+            let (id,) = parameters.get::<(u32,)>().unwrap();
+            window(id)?.close();
+            Ok(None)
+        }
+        "Focus" => {
+            let (id,) = parameters.get::<(u32,)>().unwrap();
+            window(id)?.present();
+            Ok(None)
+        }
+        "Open" => {
+            let (uri,) = parameters.get::<(String,)>().unwrap();
+            let win = match lookup_window(app, 0) {
+                Some(win) => {
+                    viewer_of(&win).webview().load_uri(&uri);
+                    win
+                }
+                None => {
+                    let win = window::Window::new(app, state.clone(), Profile::private(), None);
+                    win.load_uri(&uri);
+                    win.widget
+                }
+            };
+            win.present();
+            Ok(Some((win.id(),).to_variant()))
+        }
+        _ => Err((UNKNOWN_METHOD_ERROR, format!("unknown method: {}", method))),
+    }
+}
+
+/// Run `wv --remote COMMAND ARGS...` against the running `wv`, returning the
+/// exit status.
+pub fn run_client(command: &str, args: &[OsString], cmdline: &gio::ApplicationCommandLine) -> i32 {
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let parse_id = |arg: Option<&String>| -> Result<u32, String> {
+        let arg = arg.ok_or("missing window id")?;
+        arg.parse::<u32>()
+            .map_err(|_| format!("invalid window id: {}", arg))
+    };
+    // URLs are resolved like those given to `wv` itself
+    let parse_uri = |arg: Option<&String>| -> Result<String, String> {
+        let arg = arg.ok_or("missing URL")?;
+        Ok(cmdline.create_file_for_arg(arg).uri().to_string())
+    };
+    let call = match command {
+        "list" => Ok(("ListWindows", ().to_variant())),
+        "open" => parse_uri(args.first()).map(|uri| ("Open", (uri,).to_variant())),
+        "navigate" => parse_id(args.first())
+            .and_then(|id| parse_uri(args.get(1)).map(|uri| ("Navigate", (id, uri).to_variant()))),
+        "reload" => parse_id(args.first()).map(|id| ("Reload", (id,).to_variant())),
+        "close" => parse_id(args.first()).map(|id| ("Close", (id,).to_variant())),
+        "focus" => parse_id(args.first()).map(|id| ("Focus", (id,).to_variant())),
+        _ => Err(format!(
+            "unknown remote command: {} (list, open, navigate, reload, close or focus)",
+            command
+        )),
+    };
+    let (method, parameters) = match call {
+        Ok(call) => call,
+        Err(e) => {
+            cmdline.printerr_literal(&format!("{}\n", e));
+            return 1;
+        }
+    };
+
+    let result =
+        gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE).and_then(|connection| {
+            connection.call_sync(
+                Some(BUS_NAME),
+                OBJECT_PATH,
+                INTERFACE_NAME,
+                method,
+                Some(&parameters),
+                None,
+                gio::DBusCallFlags::NO_AUTO_START,
+                -1,
+                gio::Cancellable::NONE,
+            )
+        });
+    let reply = match result {
+        Ok(reply) => reply,
+        Err(e) => {
+            let mut e = e;
+            gio::DBusError::strip_remote_error(&mut e);
+            cmdline.printerr_literal(&format!("{}\n", e.message()));
+            return 1;
+        }
+    };
+    if let Some((windows,)) = reply.get::<(Vec<(u32, String, String)>,)>() {
+        for (id, uri, title) in windows {
+            cmdline.print_literal(&format!("{}\t{}\t{}\n", id, uri, title));
+        }
+    } else if let Some((id,)) = reply.get::<(u32,)>() {
+        cmdline.print_literal(&format!("{}\n", id));
+    }
+    0
+}
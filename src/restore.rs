@@ -0,0 +1,162 @@
+use gtk4 as gtk;
+
+use gtk::glib::translate::{from_glib_full, ToGlibPtr};
+use gtk::prelude::*;
+use gtk::{glib, Application};
+use serde_derive::{Deserialize, Serialize};
+use std::rc::Rc;
+use webkit6::prelude::*;
+use webkit6::WebViewSessionState;
+
+use crate::profile::Profile;
+use crate::settings;
+use crate::state::AppState;
+use crate::viewer::Viewer;
+use crate::window;
+
+const SESSION_FILE_NAME: &'static str = "session.toml";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SessionFile {
+    #[serde(default)]
+    windows: Vec<WindowState>,
+}
+
+/// Everything needed to bring a window back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowState {
+    pub profile: String,
+    #[serde(default)]
+    pub isolated: bool,
+    pub width: i32,
+    pub height: i32,
+    pub uri: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// base64 encoded `WebViewSessionState`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+}
+
+impl WindowState {
+    /// Take the state of a window.
+    ///
+    /// Returns None if the window has nothing worth restoring.
+    pub fn of(win: &gtk::Window) -> Option<Self> {
+        let viewer = win.child().and_downcast::<Viewer>()?;
+        let webview = viewer.webview();
+        // nothing has been loaded yet if there is no current item
+        let uri = webview
+            .back_forward_list()?
+            .current_item()?
+            .uri()?
+            .to_string();
+        if uri == "about:blank" && !webview.can_go_back() && !webview.can_go_forward() {
+            return None;
+        }
+        let profile = viewer.profile();
+        let state = webview
+            .session_state()
+            .and_then(|state| state.serialize())
+            .map(|bytes| glib::base64_encode(&bytes).to_string());
+        let (width, height) = win.default_size();
+        Some(Self {
+            profile: profile.name.clone(),
+            isolated: profile.isolated,
+            width,
+            height,
+            uri,
+            title: webview.title().map(|title| title.to_string()),
+            state,
+        })
+    }
+
+    /// Open a new window from this state.
+    pub fn open(&self, app: &Application, state: &Rc<AppState>) -> window::Window {
+        let mut profile =
+            Profile::lookup(&state.settings.borrow(), &self.profile).unwrap_or_else(|| {
+                eprintln!("profile {} is gone, use private", self.profile);
+                Profile::private()
+            });
+        profile.isolated = self.isolated;
+        let win = window::Window::new(app, state.clone(), profile, None);
+        if self.width > 0 && self.height > 0 {
+            win.widget.set_default_size(self.width, self.height);
+        }
+        win.widget.present();
+
+        let state = self.state.as_deref().and_then(|encoded| {
+            let state = decode_session_state(encoded);
+            if state.is_none() {
+                eprintln!("can't restore history of {}", self.uri);
+            }
+            state
+        });
+        win.restore_session_state(state.as_ref(), &self.uri);
+        win
+    }
+}
+
+/// Decode a session state saved by `WindowState::of`, or None if it's broken
+/// or from an incompatible version of WebKit.
+fn decode_session_state(encoded: &str) -> Option<WebViewSessionState> {
+    // base64_decode() decodes whatever it is given
+    let encoded: String = encoded.split_whitespace().collect();
+    let is_base64 = encoded.len() % 4 == 0
+        && encoded.trim_end_matches('=').len() + 2 >= encoded.len()
+        && encoded
+            .trim_end_matches('=')
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/');
+    if encoded.is_empty() || !is_base64 {
+        return None;
+    }
+    let bytes = glib::Bytes::from_owned(glib::base64_decode(&encoded));
+    // WebViewSessionState::new() doesn't expect NULL for data it can't read
+    unsafe {
+        let state = webkit6::ffi::webkit_web_view_session_state_new(bytes.to_glib_none().0);
+        if state.is_null() {
+            None
+        } else {
+            Some(from_glib_full(state))
+        }
+    }
+}
+
+/// Save the state of all windows except `closing`, and those of the private
+/// profile and isolated windows, which are never written to disk.
+pub fn save(app: &Application, closing: Option<&gtk::Window>) {
+    let windows = app
+        .windows()
+        .iter()
+        .filter(|win| closing != Some(*win))
+        .filter(|win| {
+            win.child()
+                .and_downcast::<Viewer>()
+                .map_or(false, |viewer| viewer.profile().data_dir().is_some())
+        })
+        .filter_map(WindowState::of)
+        .collect();
+    let session = SessionFile { windows };
+    match toml::to_string(&session) {
+        Ok(data) => settings::save_config_file(SESSION_FILE_NAME, &data),
+        Err(e) => {
+            dbg!(e);
+        }
+    }
+}
+
+/// Load windows saved last time.
+pub fn load() -> Vec<WindowState> {
+    let data = match settings::load_config_file(SESSION_FILE_NAME) {
+        Some(data) => data,
+        None => return Vec::new(),
+    };
+    match toml::from_str::<SessionFile>(&data) {
+        Ok(session) => session.windows,
+        Err(e) => {
+            dbg!(e);
+            Vec::new()
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use gtk4 as gtk;
+
+use gtk::gio::prelude::*;
+use gtk::gio::AppInfo;
+use gtk::glib::{GString, Regex, RegexCompileFlags, RegexMatchFlags, Uri, UriFlags};
+
+use crate::settings::{Rule, RuleAction};
+
+/// What to do with a navigation, decided by the rules.
+pub enum Decision {
+    Wv,
+    OpenWith(AppInfo),
+    Block,
+}
+
+pub fn decide(rules: &[Rule], uri: &str) -> Decision {
+    let parsed = Uri::parse(uri, UriFlags::NONE).ok();
+    for rule in rules {
+        if !matches(rule, uri, parsed.as_ref()) {
+            continue;
+        }
+        return match rule.action {
+            RuleAction::Wv => Decision::Wv,
+            RuleAction::Block => Decision::Block,
+            RuleAction::OpenWith => {
+                let id = rule.app.as_deref().unwrap_or_default();
+                match AppInfo::all()
+                    .into_iter()
+                    .find(|info| info.id().map_or(false, |i| i.as_str() == id))
+                {
+                    Some(info) => Decision::OpenWith(info),
+                    None => {
+                        eprintln!("application not found: {:?}", id);
+                        Decision::Wv
+                    }
+                }
+            }
+            RuleAction::Default => {
+                let scheme = Uri::parse_scheme(uri).unwrap_or_default();
+                match AppInfo::default_for_uri_scheme(&scheme) {
+                    // opening with myself would end up here again
+                    Some(info) if info.id() != Some(GString::from("wv.desktop")) => {
+                        Decision::OpenWith(info)
+                    }
+                    _ => Decision::Wv,
+                }
+            }
+        };
+    }
+    Decision::Wv
+}
+
+/// Mark rules whose regex doesn't compile, reporting each once, so that
+/// they don't warn on every navigation.
+pub fn check_regexes(rules: &mut [Rule]) {
+    for rule in rules {
+        if let Some(regex) = &rule.regex {
+            if let Err(e) = Regex::new(regex, RegexCompileFlags::DEFAULT, RegexMatchFlags::DEFAULT)
+            {
+                eprintln!("invalid regex in [[rules]]: {}: {}", regex, e);
+                rule.regex_invalid = true;
+            }
+        }
+    }
+}
+
+fn matches(rule: &Rule, uri: &str, parsed: Option<&Uri>) -> bool {
+    if rule.regex_invalid {
+        return false;
+    }
+    if rule.host.is_some() || rule.scheme.is_some() || rule.path_prefix.is_some() {
+        let parsed = match parsed {
+            Some(parsed) => parsed,
+            None => return false,
+        };
+        if let Some(scheme) = &rule.scheme {
+            if !parsed.scheme().eq_ignore_ascii_case(scheme) {
+                return false;
+            }
+        }
+        if let Some(host) = &rule.host {
+            match parsed.host() {
+                Some(h) if glob_match(&host.to_lowercase(), &h.to_lowercase()) => (),
+                _ => return false,
+            }
+        }
+        if let Some(path_prefix) = &rule.path_prefix {
+            if !parsed.path().starts_with(path_prefix.as_str()) {
+                return false;
+            }
+        }
+    }
+    if let Some(regex) = &rule.regex {
+        // checked by check_regexes; Regex::match_() would save compiling it
+        // again, but trips a debug assertion of glib when nothing matches
+        if !Regex::match_simple(
+            regex.as_str(),
+            uri,
+            RegexCompileFlags::DEFAULT,
+            RegexMatchFlags::DEFAULT,
+        ) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Match `text` against `pattern`, where `*` matches any string and `?` any
+/// single character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // position of the last `*` in pattern and the text position it matched up to
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Host of `uri` in lower case, the key of per-site settings.
+pub fn host(uri: &str) -> Option<String> {
+    let uri = Uri::parse(uri, UriFlags::NONE).ok()?;
+    Some(uri.host()?.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(action: RuleAction) -> Rule {
+        Rule {
+            host: None,
+            scheme: None,
+            path_prefix: None,
+            regex: None,
+            regex_invalid: false,
+            action,
+            app: None,
+        }
+    }
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("example.com", "example.com"));
+        assert!(!glob_match("example.com", "www.example.com"));
+        assert!(glob_match("*.example.com", "www.example.com"));
+        assert!(glob_match("*.example.com", "a.b.example.com"));
+        assert!(!glob_match("*.example.com", "example.com"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("ex?mple.*", "example.org"));
+        assert!(!glob_match("ex?mple", "exmple"));
+        assert!(glob_match("*a*b", "xaxxab"));
+        assert!(!glob_match("*a*b", "xaxxa"));
+    }
+
+    #[test]
+    fn decide_first_matching_rule() {
+        let mut block = rule(RuleAction::Block);
+        block.host = Some("*.Example.com".to_owned());
+        block.path_prefix = Some("/ads/".to_owned());
+        let mut wv = rule(RuleAction::Wv);
+        wv.host = Some("*.example.com".to_owned());
+        let mut block_all = rule(RuleAction::Block);
+        block_all.regex = Some("^https://".to_owned());
+        let rules = [block, wv, block_all];
+
+        assert!(matches!(
+            decide(&rules, "https://www.EXAMPLE.com/ads/1"),
+            Decision::Block
+        ));
+        // all conditions of a rule must match
+        assert!(matches!(
+            decide(&rules, "https://www.example.com/docs/"),
+            Decision::Wv
+        ));
+        assert!(matches!(
+            decide(&rules, "https://example.org/"),
+            Decision::Block
+        ));
+        assert!(matches!(
+            decide(&rules, "http://example.org/"),
+            Decision::Wv
+        ));
+    }
+
+    #[test]
+    fn decide_scheme() {
+        let mut block = rule(RuleAction::Block);
+        block.scheme = Some("MAILTO".to_owned());
+        let rules = [block];
+        assert!(matches!(
+            decide(&rules, "mailto:someone@example.com"),
+            Decision::Block
+        ));
+        assert!(matches!(
+            decide(&rules, "https://example.com/"),
+            Decision::Wv
+        ));
+        // conditions on parts of the URL don't match what can't be parsed
+        assert!(matches!(decide(&rules, "not a url"), Decision::Wv));
+    }
+
+    #[test]
+    fn invalid_regex_never_matches() {
+        let mut invalid = rule(RuleAction::Block);
+        invalid.regex = Some("(".to_owned());
+        let mut valid = rule(RuleAction::Block);
+        valid.regex = Some("^https://blocked\\.".to_owned());
+        let mut rules = [invalid, valid];
+        check_regexes(&mut rules);
+        assert!(rules[0].regex_invalid);
+        assert!(!rules[1].regex_invalid);
+        assert!(matches!(
+            decide(&rules, "https://example.com/"),
+            Decision::Wv
+        ));
+        assert!(matches!(
+            decide(&rules, "https://blocked.example.com/"),
+            Decision::Block
+        ));
+    }
+}
@@ -0,0 +1,38 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use webkit6::NetworkSession;
+
+use crate::profile::Profile;
+use crate::settings::{SessionPolicy, Settings};
+
+/// Hands out network sessions to new top-level windows.
+pub struct SessionManager {
+    settings: Rc<RefCell<Settings>>,
+    // shared sessions, keyed by profile name
+    sessions: RefCell<HashMap<String, NetworkSession>>,
+}
+
+impl SessionManager {
+    pub fn new(settings: Rc<RefCell<Settings>>) -> Self {
+        Self {
+            settings,
+            sessions: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn session_for(&self, profile: &Profile) -> NetworkSession {
+        if profile.isolated
+            || (profile.is_private()
+                && self.settings.borrow().session.policy == SessionPolicy::Isolated)
+        {
+            return profile.create_network_session();
+        }
+        self.sessions
+            .borrow_mut()
+            .entry(profile.name.clone())
+            .or_insert_with(|| profile.create_network_session())
+            .clone()
+    }
+}
@@ -4,10 +4,46 @@ use gtk::gio::prelude::*;
 use gtk::gio::{Cancellable, File};
 use gtk::{gio, glib};
 use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Settings {
     pub window: Window,
+
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+
+    #[serde(default)]
+    pub session: Session,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<Rule>,
+
+    #[serde(default)]
+    pub reopen: Reopen,
+
+    #[serde(default)]
+    pub search: Search,
+
+    #[serde(default)]
+    pub history: History,
+
+    #[serde(default)]
+    pub content_blocking: ContentBlocking,
+
+    #[serde(default)]
+    pub zoom: Zoom,
+
+    #[serde(default)]
+    pub open: Open,
+
+    #[serde(default)]
+    pub downloads: Downloads,
+
+    /// accelerators of actions, replacing the default ones
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub keybindings: BTreeMap<String, Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,6 +55,291 @@ pub struct Window {
     pub height: i32,
 }
 
+/// A named, persistent profile.
+///
+/// Data and cache are kept under `$XDG_CONFIG_HOME/wv/profiles/NAME` and
+/// `$XDG_CACHE_HOME/wv/profiles/NAME` unless overridden here.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_dir: Option<PathBuf>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(default)]
+    pub policy: SessionPolicy,
+
+    /// restore windows of the last run on start
+    #[serde(default)]
+    pub restore: bool,
+
+    /// seconds between saving open windows, for recovering after a crash
+    #[serde(default = "default_autosave_interval")]
+    pub autosave_interval: u32,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            policy: SessionPolicy::default(),
+            restore: false,
+            autosave_interval: default_autosave_interval(),
+        }
+    }
+}
+
+/// How windows of the private profile get their network session.
+///
+/// Windows of a persistent profile always share its session.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionPolicy {
+    /// all windows share one session
+    #[default]
+    Shared,
+    /// every window has a session of its own
+    Isolated,
+}
+
+/// Decides what to do on navigation to matching URLs.
+///
+/// All given conditions must match. Rules are checked in order and the first
+/// matching one wins.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Rule {
+    /// host name glob, e.g. "*.example.com"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_prefix: Option<String>,
+
+    /// regular expression matched against the whole URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+
+    /// `regex` doesn't compile, so the rule never matches
+    #[serde(skip)]
+    pub regex_invalid: bool,
+
+    pub action: RuleAction,
+
+    /// desktop file id of the application for `action = "open-with"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RuleAction {
+    /// open in wv as usual
+    Wv,
+    /// open with the application given by `app`
+    OpenWith,
+    /// open with the default handler of the URL
+    Default,
+    /// don't open at all
+    Block,
+}
+
+/// Entries of "Re-Open Page with ..." and "Open Link with ..." menus.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Reopen {
+    /// desktop file ids of browsers to list first, in this order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub browsers: Vec<String>,
+
+    /// desktop file ids of browsers not to list
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hidden: Vec<String>,
+
+    /// desktop file id of the browser used by "win.reopen-preferred"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<Command>,
+}
+
+/// A custom menu entry.
+///
+/// `{url}`, `{title}` and `{selection}` in `command` and `clipboard` are
+/// replaced with the URL, the title and the selected text of the page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Command {
+    pub label: String,
+
+    /// icon name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+
+    /// command line to run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+
+    /// text to copy to the clipboard
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clipboard: Option<String>,
+}
+
+/// Search engines used from the location entry.
+///
+/// `{}` in templates is replaced with the query.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Search {
+    /// used when the input doesn't look like a URL
+    #[serde(default = "default_search")]
+    pub default: String,
+
+    /// "KEYWORD QUERY" searches with the template of KEYWORD
+    #[serde(default)]
+    pub keywords: BTreeMap<String, String>,
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self {
+            default: default_search(),
+            keywords: BTreeMap::new(),
+        }
+    }
+}
+
+/// Browsing history, kept in the data directory of each profile.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct History {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// the number of pages to keep, 0 for unlimited
+    #[serde(default = "default_history_max_entries")]
+    pub max_entries: usize,
+
+    /// days to keep pages not visited since, 0 for unlimited
+    #[serde(default = "default_history_max_days")]
+    pub max_days: u32,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_entries: default_history_max_entries(),
+            max_days: default_history_max_days(),
+        }
+    }
+}
+
+/// Blocking with filter lists in `$XDG_CONFIG_HOME/wv/filters/`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContentBlocking {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// hosts where blocking is turned off, for pages broken by it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disabled_hosts: Vec<String>,
+}
+
+impl Default for ContentBlocking {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            disabled_hosts: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Zoom {
+    /// zoom level of sites not zoomed yet
+    #[serde(default = "default_zoom")]
+    pub default: f64,
+
+    /// zoom only text instead of the whole page
+    #[serde(default)]
+    pub text_only: bool,
+}
+
+impl Default for Zoom {
+    fn default() -> Self {
+        Self {
+            default: default_zoom(),
+            text_only: false,
+        }
+    }
+}
+
+/// Where URLs given to `wv` are loaded.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Open {
+    /// load in the most recently focused window instead of a new one
+    #[serde(default)]
+    pub reuse_window: bool,
+
+    /// load URLs given at once in one window, as its back/forward list
+    #[serde(default)]
+    pub single_window: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Downloads {
+    /// checked in order; the first matching one decides where to save
+    /// without asking
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<DownloadRule>,
+}
+
+/// Saves matching downloads in `directory`. All given conditions must match.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DownloadRule {
+    /// MIME type glob, e.g. "image/*"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+
+    /// file name extension, e.g. "zip" or "tar.gz"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extension: Option<String>,
+
+    /// host name glob of the download URL or the page it was started from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+
+    /// "~/" is the home directory; relative to the downloads directory
+    pub directory: PathBuf,
+}
+
+fn default_zoom() -> f64 {
+    1.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_history_max_entries() -> usize {
+    10000
+}
+
+fn default_history_max_days() -> u32 {
+    90
+}
+
+fn default_search() -> String {
+    "https://duckduckgo.com/?q={}".to_owned()
+}
+
+fn default_autosave_interval() -> u32 {
+    60
+}
+
 fn default_width() -> i32 {
     800
 }
@@ -26,12 +347,18 @@ fn default_height() -> i32 {
     640
 }
 
-fn get_app_config_dir() -> Option<std::path::PathBuf> {
+pub fn get_app_config_dir() -> Option<std::path::PathBuf> {
     let mut path = glib::user_config_dir();
     path.push("wv");
     Some(path)
 }
 
+pub fn get_app_cache_dir() -> Option<std::path::PathBuf> {
+    let mut path = glib::user_cache_dir();
+    path.push("wv");
+    Some(path)
+}
+
 const SETTINGS_FILE_NAME: &'static str = "settings.toml";
 
 pub fn load_settings() -> Settings {
@@ -50,7 +377,8 @@ pub fn load_settings() -> Settings {
         if let Ok((data, _)) = settings_file.load_contents(Cancellable::NONE) {
             match std::str::from_utf8(&data) {
                 Ok(s) => match toml::from_str(&s) {
-                    Ok(settings) => {
+                    Ok(mut settings) => {
+                        crate::rules::check_regexes(&mut settings.rules);
                         return settings;
                     }
                     Err(e) => {
@@ -68,6 +396,17 @@ pub fn load_settings() -> Settings {
             height: default_height(),
             width: default_width(),
         },
+        profiles: BTreeMap::new(),
+        session: Session::default(),
+        rules: Vec::new(),
+        reopen: Reopen::default(),
+        search: Search::default(),
+        history: History::default(),
+        content_blocking: ContentBlocking::default(),
+        zoom: Zoom::default(),
+        open: Open::default(),
+        downloads: Downloads::default(),
+        keybindings: BTreeMap::new(),
     }
 }
 
@@ -96,3 +435,40 @@ pub fn save_settings(settings: &Settings) {
         }
     }
 }
+
+/// Read a file in the config directory.
+pub fn load_config_file(name: &str) -> Option<String> {
+    let mut path = get_app_config_dir()?;
+    path.push(name);
+    let (data, _) = File::for_path(path).load_contents(Cancellable::NONE).ok()?;
+    match String::from_utf8(data.to_vec()) {
+        Ok(s) => Some(s),
+        Err(e) => {
+            dbg!(&e);
+            None
+        }
+    }
+}
+
+/// Write a file in the config directory, replacing the old one atomically.
+pub fn save_config_file(name: &str, data: &str) {
+    if let Some(mut path) = get_app_config_dir() {
+        let dir = File::for_path(&path);
+        if !dir.query_exists(Cancellable::NONE) {
+            dir.make_directory_with_parents(Cancellable::NONE)
+                .unwrap_or_else(|e| {
+                    dbg!(e);
+                });
+        }
+        path.push(name);
+        if let Err(e) = File::for_path(path).replace_contents(
+            data.as_bytes(),
+            None,
+            false,
+            gio::FileCreateFlags::NONE,
+            Cancellable::NONE,
+        ) {
+            dbg!(e);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::bookmarks::Bookmarks;
+use crate::closed::ClosedWindows;
+use crate::contentfilter::ContentFilters;
+use crate::downloads::Downloads;
+use crate::history::History;
+use crate::sessions::SessionManager;
+use crate::settings::Settings;
+use crate::usercontent::UserContent;
+use crate::zoom::ZoomLevels;
+
+/// State shared by all windows of the application.
+pub struct AppState {
+    pub settings: Rc<RefCell<Settings>>,
+    pub sessions: SessionManager,
+    pub closed_windows: ClosedWindows,
+    pub history: History,
+    pub bookmarks: Bookmarks,
+    pub downloads: Rc<Downloads>,
+    pub content_filters: ContentFilters,
+    pub user_content: Rc<UserContent>,
+    pub zoom_levels: ZoomLevels,
+    /// carried over to the find bar of new windows
+    pub last_find_query: Rc<RefCell<String>>,
+}
+
+impl AppState {
+    pub fn new(settings: Settings) -> Self {
+        let settings = Rc::new(RefCell::new(settings));
+        Self {
+            sessions: SessionManager::new(settings.clone()),
+            history: History::new(settings.clone()),
+            content_filters: ContentFilters::new(settings.clone()),
+            downloads: Rc::new(Downloads::load(settings.clone())),
+            user_content: UserContent::new(),
+            settings,
+            closed_windows: ClosedWindows::new(),
+            bookmarks: Bookmarks::load(),
+            zoom_levels: ZoomLevels::load(),
+            last_find_query: Rc::new(RefCell::new(String::new())),
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use gtk4 as gtk;
+
+use gtk::gio::prelude::*;
+use gtk::gio::{Cancellable, File, FileMonitor, FileMonitorEvent, FileMonitorFlags};
+use gtk::glib;
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use webkit6::{
+    UserContentInjectedFrames, UserContentManager, UserScript, UserScriptInjectionTime,
+    UserStyleLevel, UserStyleSheet,
+};
+
+use crate::settings;
+
+const SCRIPTS_DIR_NAME: &'static str = "userscripts";
+const STYLES_DIR_NAME: &'static str = "userstyles";
+
+// milliseconds to wait for an editor to finish writing files
+const RELOAD_DELAY: u64 = 200;
+
+/// Metadata block at the top of a user script or style:
+///
+/// ```text
+/// // ==UserScript==
+/// // @match https://example.com/*
+/// // @run-at document-start
+/// // ==/UserScript==
+/// ```
+#[derive(Debug, Default)]
+struct Metadata {
+    matches: Vec<String>,
+    excludes: Vec<String>,
+    run_at_start: bool,
+    top_frame_only: bool,
+    // some @include could not be converted, so an empty `matches` doesn't
+    // mean every page
+    skipped_includes: bool,
+}
+
+impl Metadata {
+    fn parse(source: &str) -> Self {
+        let mut metadata = Self::default();
+        let mut in_block = false;
+        for line in source.lines() {
+            let line = line
+                .trim()
+                .trim_start_matches("/*")
+                .trim_start_matches("//")
+                .trim_start_matches('*')
+                .trim();
+            if line.starts_with("==UserScript==") || line.starts_with("==UserStyle==") {
+                in_block = true;
+                continue;
+            }
+            if line.starts_with("==/UserScript==") || line.starts_with("==/UserStyle==") {
+                break;
+            }
+            if !in_block {
+                continue;
+            }
+            let (key, value) = match line.split_once(char::is_whitespace) {
+                Some((key, value)) => (key, value.trim()),
+                None => (line, ""),
+            };
+            match key {
+                "@match" if !value.is_empty() => metadata.matches.push(value.to_owned()),
+                "@include" if !value.is_empty() => match glob_to_match_pattern(value) {
+                    Some(pattern) => metadata.matches.push(pattern),
+                    None => {
+                        eprintln!("unsupported @include, ignored: {}", value);
+                        metadata.skipped_includes = true;
+                    }
+                },
+                "@exclude-match" if !value.is_empty() => metadata.excludes.push(value.to_owned()),
+                "@exclude" if !value.is_empty() => match glob_to_match_pattern(value) {
+                    Some(pattern) => metadata.excludes.push(pattern),
+                    None => eprintln!("unsupported @exclude, ignored: {}", value),
+                },
+                "@run-at" => metadata.run_at_start = value == "document-start",
+                "@noframes" => metadata.top_frame_only = true,
+                _ => (),
+            }
+        }
+        metadata
+    }
+
+    // none of its @include could be converted
+    fn matches_nothing(&self) -> bool {
+        self.matches.is_empty() && self.skipped_includes
+    }
+
+    fn injected_frames(&self) -> UserContentInjectedFrames {
+        if self.top_frame_only {
+            UserContentInjectedFrames::TopFrame
+        } else {
+            UserContentInjectedFrames::AllFrames
+        }
+    }
+
+    // patterns are the same as WebKit's, "scheme://host/path" with "*"
+    fn allow_list(&self) -> Vec<&str> {
+        self.matches
+            .iter()
+            .map(|s| s.as_str())
+            // "*" alone means everything, which is what an empty list does
+            .filter(|s| *s != "*")
+            .collect()
+    }
+
+    fn block_list(&self) -> Vec<&str> {
+        self.excludes.iter().map(|s| s.as_str()).collect()
+    }
+}
+
+/// Convert a Greasemonkey @include or @exclude glob to a WebKit match
+/// pattern, "http*://*.example.com/*" to "*://*.example.com/*" for example.
+/// Regular expressions and globs with "*" in the middle of the host can't be
+/// expressed.
+fn glob_to_match_pattern(glob: &str) -> Option<String> {
+    if glob == "*" {
+        return Some(glob.to_owned());
+    }
+    let (scheme, rest) = glob.split_once("://")?;
+    let scheme = match scheme {
+        "*" | "http*" => "*",
+        "http" | "https" | "file" | "ftp" => scheme,
+        _ => return None,
+    };
+    let (host, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    if host.is_empty() && scheme != "file" {
+        return None;
+    }
+    if host != "*"
+        && host
+            .trim_start_matches("*.")
+            .contains(|c: char| c == '*' || c == ':')
+    {
+        return None;
+    }
+    Some(format!("{}://{}{}", scheme, host, path))
+}
+
+/// User scripts and styles from the config directory, added to the content
+/// manager of every web view and reloaded when the files change.
+pub struct UserContent {
+    scripts: RefCell<Vec<UserScript>>,
+    styles: RefCell<Vec<UserStyleSheet>>,
+    managers: RefCell<Vec<glib::WeakRef<UserContentManager>>>,
+    monitors: RefCell<Vec<FileMonitor>>,
+    reload_scheduled: Cell<bool>,
+}
+
+impl UserContent {
+    pub fn new() -> Rc<Self> {
+        Rc::new(Self {
+            scripts: RefCell::new(Vec::new()),
+            styles: RefCell::new(Vec::new()),
+            managers: RefCell::new(Vec::new()),
+            monitors: RefCell::new(Vec::new()),
+            reload_scheduled: Cell::new(false),
+        })
+    }
+
+    /// Load files and start watching them. GTK has to be initialized.
+    pub fn start(self: &Rc<Self>) {
+        self.load();
+        self.watch();
+    }
+
+    /// Add user scripts and styles to `manager`, now and after reloading.
+    pub fn attach(&self, manager: &UserContentManager) {
+        for script in self.scripts.borrow().iter() {
+            manager.add_script(script);
+        }
+        for style in self.styles.borrow().iter() {
+            manager.add_style_sheet(style);
+        }
+        let mut managers = self.managers.borrow_mut();
+        managers.retain(|m| m.upgrade().is_some());
+        managers.push(manager.downgrade());
+    }
+
+    fn load(&self) {
+        let (scripts, styles) = match settings::get_app_config_dir() {
+            Some(dir) => (
+                read_dir(&dir.join(SCRIPTS_DIR_NAME), "js")
+                    .iter()
+                    .map(|source| (source, Metadata::parse(source)))
+                    .filter(|(_, metadata)| !metadata.matches_nothing())
+                    .map(|(source, metadata)| {
+                        UserScript::new(
+                            source,
+                            metadata.injected_frames(),
+                            if metadata.run_at_start {
+                                UserScriptInjectionTime::Start
+                            } else {
+                                UserScriptInjectionTime::End
+                            },
+                            &metadata.allow_list(),
+                            &metadata.block_list(),
+                        )
+                    })
+                    .collect(),
+                read_dir(&dir.join(STYLES_DIR_NAME), "css")
+                    .iter()
+                    .map(|source| (source, Metadata::parse(source)))
+                    .filter(|(_, metadata)| !metadata.matches_nothing())
+                    .map(|(source, metadata)| {
+                        UserStyleSheet::new(
+                            source,
+                            metadata.injected_frames(),
+                            UserStyleLevel::User,
+                            &metadata.allow_list(),
+                            &metadata.block_list(),
+                        )
+                    })
+                    .collect(),
+            ),
+            None => (Vec::new(), Vec::new()),
+        };
+        self.scripts.replace(scripts);
+        self.styles.replace(styles);
+    }
+
+    fn reload(&self) {
+        self.reload_scheduled.set(false);
+        let managers: Vec<UserContentManager> = self
+            .managers
+            .borrow()
+            .iter()
+            .filter_map(|m| m.upgrade())
+            .collect();
+        // other scripts may have been added to managers, so remove only ours
+        for manager in &managers {
+            for script in self.scripts.borrow().iter() {
+                manager.remove_script(script);
+            }
+            for style in self.styles.borrow().iter() {
+                manager.remove_style_sheet(style);
+            }
+        }
+        self.load();
+        self.managers.borrow_mut().clear();
+        for manager in &managers {
+            self.attach(manager);
+        }
+    }
+
+    fn watch(self: &Rc<Self>) {
+        let dir = match settings::get_app_config_dir() {
+            Some(dir) => dir,
+            None => return,
+        };
+        for name in [SCRIPTS_DIR_NAME, STYLES_DIR_NAME] {
+            let monitor = match File::for_path(dir.join(name))
+                .monitor_directory(FileMonitorFlags::WATCH_MOVES, Cancellable::NONE)
+            {
+                Ok(monitor) => monitor,
+                Err(e) => {
+                    eprintln!("failed to watch {}: {}", name, e);
+                    continue;
+                }
+            };
+            let this: Weak<Self> = Rc::downgrade(self);
+            monitor.connect_changed(move |_monitor, _file, _other_file, event| {
+                // "changed" is followed by "changes done"
+                if event == FileMonitorEvent::Changed {
+                    return;
+                }
+                let this = match this.upgrade() {
+                    Some(this) => this,
+                    None => return,
+                };
+                if this.reload_scheduled.replace(true) {
+                    return;
+                }
+                let this = Rc::downgrade(&this);
+                glib::timeout_add_local_once(
+                    std::time::Duration::from_millis(RELOAD_DELAY),
+                    move || {
+                        if let Some(this) = this.upgrade() {
+                            this.reload();
+                        }
+                    },
+                );
+            });
+            self.monitors.borrow_mut().push(monitor);
+        }
+    }
+}
+
+// contents of files with `extension` in `dir`, sorted by file name
+fn read_dir(dir: &Path, extension: &str) -> Vec<String> {
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == extension))
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+    paths.sort();
+    paths
+        .iter()
+        .filter_map(|path| match std::fs::read_to_string(path) {
+            Ok(source) => Some(source),
+            Err(e) => {
+                eprintln!("failed to read {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        let convert = |glob| glob_to_match_pattern(glob);
+        assert_eq!(convert("*").as_deref(), Some("*"));
+        assert_eq!(
+            convert("http*://*.example.com/*").as_deref(),
+            Some("*://*.example.com/*")
+        );
+        assert_eq!(
+            convert("https://example.com").as_deref(),
+            Some("https://example.com/")
+        );
+        assert_eq!(
+            convert("https://example.com/login*").as_deref(),
+            Some("https://example.com/login*")
+        );
+        assert_eq!(convert("file:///home/*").as_deref(), Some("file:///home/*"));
+        assert_eq!(convert("*example.com*"), None);
+        assert_eq!(convert("https://www.example.*/*"), None);
+        assert_eq!(convert("https://*example.com/*"), None);
+        assert_eq!(convert("/^https?://example\\.com//"), None);
+    }
+
+    #[test]
+    fn metadata() {
+        let metadata = Metadata::parse(
+            "// ==UserScript==\n\
+             // @include http*://example.com/*\n\
+             // @include /example/\n\
+             // @exclude *://example.com/login*\n\
+             // @run-at document-start\n\
+             // ==/UserScript==\n\
+             // @include https://after.example/*\n",
+        );
+        assert_eq!(metadata.allow_list(), ["*://example.com/*"]);
+        assert_eq!(metadata.block_list(), ["*://example.com/login*"]);
+        assert!(metadata.run_at_start);
+        assert!(!metadata.matches_nothing());
+
+        let metadata =
+            Metadata::parse("// ==UserScript==\n// @include /example/\n// ==/UserScript==\n");
+        assert!(metadata.matches_nothing());
+    }
+}
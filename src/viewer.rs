@@ -1,34 +1,58 @@
 use gtk4 as gtk;
 
-use gtk::glib;
 use gtk::subclass::prelude::*;
+use gtk::{gio, glib};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 
 use webkit6::prelude::*;
-use webkit6::{CookieAcceptPolicy, NetworkSession, WebView};
+use webkit6::{LoadEvent, UserContentManager, WebView};
+
+use crate::profile::Profile;
+use crate::state::AppState;
+
+// how long load_uris waits for each URL to be committed
+const COMMIT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
 
 mod imp {
     use gtk::glib;
     use gtk::glib::clone;
     use gtk::subclass::prelude::*;
-    use gtk::{Align, Label, Orientation, Overlay, ProgressBar, SearchBar, SearchEntry};
+    use gtk::{
+        gdk, Align, Button, Label, Orientation, Overlay, ProgressBar, SearchBar, SearchEntry,
+        ToggleButton,
+    };
     use gtk4 as gtk;
-    use std::cell::OnceCell;
+    use std::cell::{Cell, OnceCell, RefCell};
+    use std::rc::Rc;
     use webkit6::prelude::*;
     use webkit6::{FindOptions, WebView};
 
+    use crate::profile::Profile;
+
     #[derive(glib::Properties, Debug)]
     #[properties(wrapper_type = super::Viewer)]
     pub struct Viewer {
         box_: gtk::Box,
         #[property(get)]
         pub webview: OnceCell<WebView>,
+        pub(super) profile: OnceCell<Profile>,
         pub(super) overlay: Overlay,
         progress_bar: ProgressBar,
         status_bar: Label,
         #[property(get)]
         pub search_bar: SearchBar,
-        search_entry: SearchEntry,
+        pub(super) search_entry: SearchEntry,
+        previous_button: Button,
+        next_button: Button,
+        case_button: ToggleButton,
+        word_button: ToggleButton,
         match_count_label: Label,
+        // 1-based index of the current match, 0 if unknown
+        match_index: Cell<u32>,
+        match_count: Cell<u32>,
+        // the last query searched in any window
+        pub(super) last_query: OnceCell<Rc<RefCell<String>>>,
         alert_revealer: gtk::Revealer,
         alert_label: Label,
     }
@@ -73,18 +97,43 @@ mod imp {
             search_entry.set_halign(Align::Start);
             search_entry.set_hexpand(true);
 
+            let previous_button = Button::builder()
+                .icon_name("go-up-symbolic")
+                .tooltip_text("previous match (Shift+Enter)")
+                .build();
+            let next_button = Button::builder()
+                .icon_name("go-down-symbolic")
+                .tooltip_text("next match (Enter)")
+                .build();
+            let case_button = ToggleButton::builder()
+                .label("Aa")
+                .tooltip_text("match case")
+                .build();
+            let word_button = ToggleButton::builder()
+                .label("ab|")
+                .tooltip_text("match at word starts")
+                .build();
+
             let match_count_label = Label::new(None);
             match_count_label.set_halign(Align::End);
 
             Self {
                 box_,
                 webview: OnceCell::new(),
+                profile: OnceCell::new(),
                 overlay,
                 progress_bar,
                 status_bar,
                 search_bar,
                 search_entry,
+                previous_button,
+                next_button,
+                case_button,
+                word_button,
                 match_count_label,
+                match_index: Cell::new(0),
+                match_count: Cell::new(0),
+                last_query: OnceCell::new(),
                 alert_revealer,
                 alert_label,
             }
@@ -115,6 +164,13 @@ mod imp {
             self.search_bar.set_child(Some(&search_box));
             self.search_bar.connect_entry(&self.search_entry);
             search_box.prepend(&self.search_entry);
+            let navigation_box = gtk::Box::new(Orientation::Horizontal, 0);
+            navigation_box.add_css_class("linked");
+            navigation_box.append(&self.previous_button);
+            navigation_box.append(&self.next_button);
+            search_box.append(&navigation_box);
+            search_box.append(&self.case_button);
+            search_box.append(&self.word_button);
             search_box.append(&self.match_count_label);
 
             //self.setup_callbacks();
@@ -178,51 +234,73 @@ mod imp {
 
             let find_controller = webview.find_controller().unwrap();
             self.search_entry.connect_activate(glib::clone!(
-                #[weak]
-                find_controller,
-                move |search_entry| {
-                    let search_text = search_entry.text();
-                    match find_controller.search_text() {
-                        Some(s) if s == search_text => {
-                            find_controller.search_next();
-                        }
-                        _ => {
-                            find_controller.count_matches(
-                                &search_text,
-                                (FindOptions::WRAP_AROUND | FindOptions::CASE_INSENSITIVE).bits(),
-                                std::u32::MAX,
-                            );
-                            find_controller.search(
-                                &search_text,
-                                (FindOptions::WRAP_AROUND | FindOptions::CASE_INSENSITIVE).bits(),
-                                std::u32::MAX,
-                            );
-                        }
-                    }
+                #[weak(rename_to = this)]
+                self,
+                move |_search_entry| {
+                    this.search_next(false);
                 }
             ));
-            self.search_entry.connect_search_changed(glib::clone!(
-                #[weak]
-                find_controller,
+            self.search_entry.connect_next_match(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_search_entry| {
+                    this.search_next(false);
+                }
+            ));
+            self.search_entry.connect_previous_match(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_search_entry| {
+                    this.search_next(true);
+                }
+            ));
+            let key_controller = gtk::EventControllerKey::new();
+            key_controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+            key_controller.connect_key_pressed(glib::clone!(
                 #[weak(rename_to = this)]
                 self,
-                move |search_entry| {
-                    let search_text = search_entry.text();
-                    if search_text.is_empty() {
-                        this.match_count_label.set_label("");
-                        find_controller.search_finish();
+                #[upgrade_or]
+                glib::Propagation::Proceed,
+                move |_controller, key, _keycode, state| {
+                    if (key == gdk::Key::Return || key == gdk::Key::KP_Enter)
+                        && state.contains(gdk::ModifierType::SHIFT_MASK)
+                    {
+                        this.search_next(true);
+                        glib::Propagation::Stop
                     } else {
-                        find_controller.count_matches(
-                            &search_text,
-                            (FindOptions::WRAP_AROUND | FindOptions::CASE_INSENSITIVE).bits(),
-                            std::u32::MAX,
-                        );
-                        find_controller.search(
-                            &search_text,
-                            (FindOptions::WRAP_AROUND | FindOptions::CASE_INSENSITIVE).bits(),
-                            std::u32::MAX,
-                        );
+                        glib::Propagation::Proceed
+                    }
+                }
+            ));
+            self.search_entry.add_controller(key_controller);
+            self.previous_button.connect_clicked(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_button| {
+                    this.search_next(true);
+                }
+            ));
+            self.next_button.connect_clicked(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_button| {
+                    this.search_next(false);
+                }
+            ));
+            for button in [&self.case_button, &self.word_button] {
+                button.connect_toggled(glib::clone!(
+                    #[weak(rename_to = this)]
+                    self,
+                    move |_button| {
+                        this.search();
                     }
+                ));
+            }
+            self.search_entry.connect_search_changed(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_search_entry| {
+                    this.search();
                 }
             ));
             self.search_entry.connect_stop_search(glib::clone!(
@@ -231,7 +309,9 @@ mod imp {
                 #[weak(rename_to = this)]
                 self,
                 move |_search_entry| {
-                    this.match_count_label.set_label("");
+                    this.match_count.set(0);
+                    this.match_index.set(0);
+                    this.update_match_label();
                     find_controller.search_finish();
                 }
             ));
@@ -239,8 +319,33 @@ mod imp {
                 #[weak(rename_to = this)]
                 self,
                 move |_find_controller, match_count| {
-                    this.match_count_label
-                        .set_label(&format!("{} matches", match_count));
+                    this.match_count.set(match_count);
+                    this.update_match_label();
+                }
+            ));
+            find_controller.connect_found_text(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_find_controller, match_count| {
+                    this.match_count.set(match_count);
+                    // the page may have changed since the index was counted
+                    if this.match_index.get() == 0 || this.match_index.get() > match_count {
+                        this.match_index.set(1);
+                    }
+                    this.search_entry.remove_css_class("error");
+                    this.update_match_label();
+                }
+            ));
+            find_controller.connect_failed_to_find_text(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_find_controller| {
+                    this.match_count.set(0);
+                    this.match_index.set(0);
+                    if !this.search_entry.text().is_empty() {
+                        this.search_entry.add_css_class("error");
+                    }
+                    this.update_match_label();
                 }
             ));
 
@@ -261,6 +366,80 @@ mod imp {
                 }
             ));
         }
+
+        fn find_options(&self) -> u32 {
+            let mut options = FindOptions::WRAP_AROUND;
+            if !self.case_button.is_active() {
+                options |= FindOptions::CASE_INSENSITIVE;
+            }
+            if self.word_button.is_active() {
+                options |= FindOptions::AT_WORD_STARTS;
+            }
+            options.bits()
+        }
+
+        // start a new search with the text and options in the find bar
+        fn search(&self) {
+            let find_controller = match self.webview.get().and_then(|w| w.find_controller()) {
+                Some(find_controller) => find_controller,
+                None => return,
+            };
+            let search_text = self.search_entry.text();
+            self.search_entry.remove_css_class("error");
+            if search_text.is_empty() {
+                self.match_count.set(0);
+                self.update_match_label();
+                find_controller.search_finish();
+                return;
+            }
+            if let Some(last_query) = self.last_query.get() {
+                last_query.replace(search_text.to_string());
+            }
+            self.match_index.set(1);
+            find_controller.count_matches(&search_text, self.find_options(), std::u32::MAX);
+            find_controller.search(&search_text, self.find_options(), std::u32::MAX);
+        }
+
+        pub(super) fn search_next(&self, backwards: bool) {
+            let find_controller = match self.webview.get().and_then(|w| w.find_controller()) {
+                Some(find_controller) => find_controller,
+                None => return,
+            };
+            let search_text = self.search_entry.text();
+            match find_controller.search_text() {
+                Some(s) if s == search_text && find_controller.options() == self.find_options() => {
+                    // WebKit doesn't tell which match is selected, so follow
+                    // it around, wrapping as FindOptions::WRAP_AROUND does
+                    let (index, count) = (self.match_index.get(), self.match_count.get());
+                    if backwards {
+                        if count > 0 {
+                            self.match_index.set((index + 2 * count - 2) % count + 1);
+                        }
+                        find_controller.search_previous();
+                    } else {
+                        if count > 0 {
+                            self.match_index.set(index % count + 1);
+                        }
+                        find_controller.search_next();
+                    }
+                }
+                _ => self.search(),
+            }
+        }
+
+        fn update_match_label(&self) {
+            let (index, count) = (self.match_index.get(), self.match_count.get());
+            let label = if self.search_entry.text().is_empty() {
+                String::new()
+            } else if count == 0 {
+                "no matches".to_owned()
+            } else if index == 0 || index > count {
+                format!("{} matches", count)
+            } else {
+                format!("{} of {}", index, count)
+            };
+            self.match_count_label.set_label(&label);
+        }
     }
 }
 
@@ -271,31 +450,31 @@ glib::wrapper! {
 }
 
 impl Viewer {
-    pub fn new(related_view: Option<&WebView>) -> Self {
+    pub fn new(related_view: Option<&WebView>, state: &AppState, profile: &Profile) -> Self {
         let obj: Self = glib::Object::builder().build();
         let imp = obj.imp();
 
-        let mut builder = WebView::builder();
+        // not shared with the related view, as content filters are applied per page
+        let mut builder = WebView::builder().user_content_manager(&UserContentManager::new());
         if let Some(related_view) = related_view {
             builder = builder.related_view(related_view);
         } else {
-            let network_session = NetworkSession::new_ephemeral();
-            network_session
-                .cookie_manager()
-                .unwrap()
-                .set_accept_policy(CookieAcceptPolicy::NoThirdParty);
-            network_session.set_itp_enabled(true);
-            if let Some(website_data_manager) = network_session.website_data_manager() {
-                website_data_manager.set_favicons_enabled(true);
-            }
-
+            let network_session = state.sessions.session_for(profile);
             builder = builder.network_session(&network_session);
         }
         let webview = builder.build();
+        // before the first request; switched per site as pages start loading
+        state.content_filters.apply(&webview, "");
         let settings = WebViewExt::settings(&webview).unwrap();
         settings.set_enable_smooth_scrolling(true);
         settings.set_enable_back_forward_navigation_gestures(true);
 
+        imp.profile
+            .set(profile.clone())
+            .expect("newly created object should not have profile");
+        imp.last_query
+            .set(state.last_find_query.clone())
+            .expect("newly created object should not have last query");
         imp.overlay.set_child(Some(&webview));
         imp.webview
             .set(webview)
@@ -304,4 +483,143 @@ impl Viewer {
 
         obj
     }
+
+    /// Show the find bar and search for `text`, or the last query if the
+    /// find bar is empty.
+    pub fn start_search(&self, text: Option<&str>) {
+        let imp = self.imp();
+        imp.search_bar.set_search_mode(true);
+        let text = match text {
+            Some(text) => Some(text.to_owned()),
+            None if imp.search_entry.text().is_empty() => imp
+                .last_query
+                .get()
+                .map(|last_query| last_query.borrow().clone()),
+            None => None,
+        };
+        if let Some(text) = text.filter(|text| !text.is_empty()) {
+            imp.search_entry.set_text(&text);
+        }
+        imp.search_entry.grab_focus();
+        imp.search_entry.select_region(0, -1);
+    }
+
+    /// Search for the text selected in the page.
+    pub fn search_selection(&self) {
+        let webview = self.webview();
+        glib::MainContext::default().spawn_local(glib::clone!(
+            #[weak(rename_to = this)]
+            self,
+            async move {
+                match webview
+                    .evaluate_javascript_future("window.getSelection().toString()", None, None)
+                    .await
+                {
+                    Ok(value) => this.start_search(Some(value.to_str().trim())),
+                    Err(e) => eprintln!("failed to get selection: {:?}", e),
+                }
+            }
+        ));
+    }
+
+    /// Load `uris` one after another, so that the back/forward list holds
+    /// them all, then go back to the first one. Stops if another page is
+    /// opened in the meantime, so as not to replace it.
+    pub fn load_uris(&self, uris: Vec<String>) {
+        let webview = self.webview();
+        glib::MainContext::default().spawn_local(async move {
+            let mut committed = 0;
+            // where the last of `uris` ended up, redirects included
+            let mut loaded: Option<glib::GString> = None;
+            for uri in &uris {
+                if loaded.is_some() && webview.uri() != loaded {
+                    return;
+                }
+                if load_committed(&webview, uri).await {
+                    committed += 1;
+                }
+                loaded = webview.uri();
+            }
+            if committed > 1 {
+                if let Some(item) = webview
+                    .back_forward_list()
+                    .and_then(|list| list.nth_item(1 - committed))
+                {
+                    webview.go_to_back_forward_list_item(&item);
+                }
+            }
+        });
+    }
+
+    pub fn profile(&self) -> Profile {
+        self.imp()
+            .profile
+            .get()
+            .cloned()
+            .unwrap_or_else(Profile::private)
+    }
+}
+
+/// Load `uri` and wait until it is committed, returning false if it failed
+/// or timed out before that.
+async fn load_committed(webview: &WebView, uri: &str) -> bool {
+    let handlers = Rc::new(RefCell::new(Vec::new()));
+    let timeout = Rc::new(Cell::new(None));
+    let uri = uri.to_owned();
+    let committed = gio::GioFuture::new(webview, {
+        let handlers = handlers.clone();
+        let timeout = timeout.clone();
+        move |webview: &WebView, _cancellable, send| {
+            let send = RefCell::new(Some(send));
+            let resolve = Rc::new(move |committed: bool| {
+                if let Some(send) = send.borrow_mut().take() {
+                    send.resolve(committed);
+                }
+            });
+            handlers
+                .borrow_mut()
+                .push(webview.connect_load_failed(glib::clone!(
+                    #[strong]
+                    resolve,
+                    move |_webview, _event, _uri, _error| {
+                        resolve(false);
+                        false
+                    }
+                )));
+            handlers
+                .borrow_mut()
+                .push(webview.connect_load_changed(glib::clone!(
+                    #[strong]
+                    resolve,
+                    move |_webview, event| {
+                        // Finished without Committed when the load was handed
+                        // over to another application
+                        if event == LoadEvent::Committed || event == LoadEvent::Finished {
+                            resolve(event == LoadEvent::Committed);
+                        }
+                    }
+                )));
+            // a stalled server shouldn't hold up the rest
+            timeout.set(Some(glib::timeout_add_local_once(
+                COMMIT_TIMEOUT,
+                glib::clone!(
+                    #[strong]
+                    timeout,
+                    move || {
+                        timeout.take();
+                        resolve(false);
+                    }
+                ),
+            )));
+            webview.load_uri(&uri);
+        }
+    })
+    .await;
+    for handler in handlers.take() {
+        webview.disconnect(handler);
+    }
+    if let Some(source) = timeout.take() {
+        source.remove();
+    }
+    committed
 }
@@ -1,32 +1,55 @@
 use gtk4 as gtk;
 
 use gtk::prelude::*;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
-use gtk::gio::AppInfo;
 use gtk::glib::{clone, GString};
 use gtk::{gdk, gio, glib};
 use gtk::{
-    gio::{File, SimpleAction},
-    Align, Application, ApplicationWindow, Button, HeaderBar, Image, Label, MenuButton,
-    Orientation, Popover,
+    gio::SimpleAction, Align, Application, ApplicationWindow, Button, HeaderBar, Image, Label,
+    MenuButton, Orientation, Popover,
 };
 use webkit6::prelude::*;
 use webkit6::{
-    ContextMenu, ContextMenuItem, NavigationPolicyDecision, NavigationType, PolicyDecisionType,
-    WebView,
+    ContextMenu, ContextMenuItem, LoadEvent, NavigationPolicyDecision, NavigationType,
+    PolicyDecisionType, WebView, WebViewSessionState,
 };
 
+use crate::bookmarkspopover;
+use crate::downloadspopover;
 use crate::favicontitle;
+use crate::hints::{self, HintTarget};
+use crate::history::{self, HistoryStore};
+use crate::historywindow;
+use crate::keybindings;
+use crate::launcher;
+use crate::location;
+use crate::options::{ContentType, Options};
+use crate::palette;
+use crate::profile::Profile;
+use crate::rules;
 use crate::settings::Settings;
+use crate::state::AppState;
 use crate::viewer;
+use crate::zoom;
 
 pub struct Window {
     pub widget: ApplicationWindow,
     application: Application,
     pub settings: Rc<RefCell<Settings>>,
+    state: Rc<AppState>,
+    profile: Profile,
+    // None if history is not kept for the profile
+    history: Option<Rc<HistoryStore>>,
+    // whether size changes should be saved as the default window size
+    save_size: Rc<Cell<bool>>,
+    // zoom level given on the command line, used instead of the remembered one
+    zoom_override: Rc<Cell<Option<f64>>>,
     favicontitle: favicontitle::FaviconTitle,
+    star_button: MenuButton,
+    bookmarks_button: MenuButton,
+    zoom_button: MenuButton,
     back_button: Button,
     forward_button: Button,
     reload_or_stop_button: Button,
@@ -36,9 +59,12 @@ pub struct Window {
 impl Window {
     pub fn new(
         app: &Application,
-        settings: Rc<RefCell<Settings>>,
+        state: Rc<AppState>,
+        profile: Profile,
         related_view: Option<&WebView>,
     ) -> Self {
+        let settings = state.settings.clone();
+
         let win = ApplicationWindow::new(app);
         win.set_title(Some("Web View"));
         win.set_default_size(
@@ -46,11 +72,24 @@ impl Window {
             settings.borrow().window.height,
         );
 
-        let viewer = viewer::Viewer::new(related_view);
+        let viewer = viewer::Viewer::new(related_view, &state, &profile);
+        if let Some(manager) = viewer.webview().user_content_manager() {
+            state.user_content.attach(&manager);
+        }
         win.set_child(Some(&viewer));
 
         let favicontitle = favicontitle::FaviconTitle::new();
-        let header = HeaderBar::builder().title_widget(&favicontitle).build();
+        let star_button = MenuButton::builder()
+            .icon_name("non-starred-symbolic")
+            .tooltip_text("bookmark this page")
+            .has_frame(false)
+            .valign(Align::Center)
+            .build();
+        bookmarkspopover::build_star_popover(&star_button, state.clone(), &viewer.webview());
+        let title_box = gtk::Box::new(Orientation::Horizontal, 4);
+        title_box.append(&favicontitle);
+        title_box.append(&star_button);
+        let header = HeaderBar::builder().title_widget(&title_box).build();
         header.set_show_title_buttons(true);
         win.set_titlebar(Some(&header));
 
@@ -78,6 +117,82 @@ impl Window {
         menu_button.set_tooltip_text(Some("re-open page with ..."));
         header.pack_end(&menu_button);
 
+        let recent_menu = gio::Menu::new();
+        recent_menu.append_section(None, &state.closed_windows.menu());
+        let history_section = gio::Menu::new();
+        history_section.append(Some("Show History"), Some("win.history"));
+        recent_menu.append_section(None, &history_section);
+        let closed_button = MenuButton::builder()
+            .icon_name("document-open-recent-symbolic")
+            .tooltip_text("recently closed windows and history")
+            .menu_model(&recent_menu)
+            .build();
+        header.pack_end(&closed_button);
+
+        let open_bookmark: Rc<dyn Fn(&str, bool)> = Rc::new(clone!(
+            #[weak]
+            app,
+            #[weak]
+            viewer,
+            #[strong]
+            state,
+            #[strong]
+            profile,
+            move |uri: &str, new_window: bool| {
+                if new_window {
+                    let mut profile = profile.clone();
+                    profile.isolated = false;
+                    let win = Window::new(&app, state.clone(), profile, None);
+                    win.widget.present();
+                    win.load_uri(uri);
+                } else {
+                    let webview = viewer.webview();
+                    webview.load_uri(uri);
+                    webview.grab_focus();
+                }
+            }
+        ));
+        let bookmarks_button = MenuButton::builder()
+            .icon_name("user-bookmarks-symbolic")
+            .tooltip_text("bookmarks")
+            .popover(&bookmarkspopover::build_bookmarks_popover(
+                state.clone(),
+                open_bookmark,
+            ))
+            .build();
+        header.pack_end(&bookmarks_button);
+
+        let downloads_button = MenuButton::builder()
+            .icon_name("folder-download-symbolic")
+            .tooltip_text("downloads")
+            .build();
+        downloadspopover::build_downloads_popover(
+            &downloads_button,
+            state.clone(),
+            &viewer.webview(),
+        );
+        header.pack_end(&downloads_button);
+
+        let zoom_menu = gio::Menu::new();
+        let zoom_section = gio::Menu::new();
+        zoom_section.append(Some("Zoom In"), Some("win.zoom-in"));
+        zoom_section.append(Some("Zoom Out"), Some("win.zoom-out"));
+        zoom_section.append(Some("Reset Zoom"), Some("win.zoom-reset"));
+        zoom_menu.append_section(None, &zoom_section);
+        let zoom_text_only_section = gio::Menu::new();
+        zoom_text_only_section.append(Some("Zoom Text Only"), Some("win.zoom-text-only"));
+        zoom_menu.append_section(None, &zoom_text_only_section);
+        // an icon at 100%, the level otherwise
+        let zoom_button = MenuButton::builder()
+            .icon_name("zoom-original-symbolic")
+            .tooltip_text("zoom")
+            .menu_model(&zoom_menu)
+            .build();
+        header.pack_end(&zoom_button);
+        WebViewExt::settings(&viewer.webview())
+            .unwrap()
+            .set_zoom_text_only(settings.borrow().zoom.text_only);
+
         let menu_popover = Popover::new();
         menu_button.set_popover(Some(&menu_popover));
 
@@ -94,17 +209,12 @@ impl Window {
         let label = Label::new(Some("Re-Open Page with ..."));
         menu_box.append(&label);
 
-        let browsers = AppInfo::recommended_for_type("x-scheme-handler/http");
-        for info in browsers.iter() {
-            if info.id() == Some(GString::from("wv.desktop")) {
-                // skip myself
-                continue;
-            };
+        for target in launcher::targets(&settings.borrow().reopen) {
             let hbox = gtk::Box::new(Orientation::Horizontal, 4);
-            if let Some(icon) = info.icon() {
+            if let Some(icon) = target.icon() {
                 hbox.prepend(&Image::from_gicon(&icon));
             }
-            hbox.append(&Label::new(Some(&info.name())));
+            hbox.append(&Label::new(Some(&target.label())));
             let button = Button::builder()
                 .has_frame(false)
                 .child(&hbox)
@@ -115,29 +225,35 @@ impl Window {
             menu_box.append(&button);
 
             button.connect_clicked(clone!(
-                #[strong]
-                info,
                 #[weak]
                 viewer,
                 #[weak]
                 menu_popover,
                 move |_button| {
-                    if let Some(uri) = viewer.webview().uri() {
-                        let context = gdk::Display::default().map(|d| d.app_launch_context());
-                        if let Err(e) = info.launch_uris(&[&uri], context.as_ref()) {
-                            eprintln!("{:?}", e);
-                        }
+                    let webview = viewer.webview();
+                    if let Some(uri) = webview.uri() {
+                        let title = webview.title().unwrap_or_default();
+                        target.launch(&webview, &uri, &title);
                     }
                     menu_popover.popdown();
                 }
             ));
         }
 
+        let history = state.history.store_for(&profile);
         let this = Self {
             widget: win,
             application: app.clone(),
             settings,
+            state,
+            profile,
+            history,
+            save_size: Rc::new(Cell::new(true)),
+            zoom_override: Rc::new(Cell::new(None)),
             favicontitle,
+            star_button,
+            bookmarks_button,
+            zoom_button,
             back_button,
             forward_button,
             reload_or_stop_button,
@@ -151,47 +267,64 @@ impl Window {
         self.widget.connect_default_height_notify(glib::clone!(
             #[strong(rename_to = settings)]
             self.settings,
+            #[strong(rename_to = save_size)]
+            self.save_size,
             move |win| {
-                let height = win.default_height();
-                (*settings.borrow_mut()).window.height = height;
+                if save_size.get() {
+                    let height = win.default_height();
+                    (*settings.borrow_mut()).window.height = height;
+                }
             }
         ));
         self.widget.connect_default_width_notify(glib::clone!(
             #[strong(rename_to = settings)]
             self.settings,
+            #[strong(rename_to = save_size)]
+            self.save_size,
             move |win| {
-                let width = win.default_width();
-                (*settings.borrow_mut()).window.width = width;
+                if save_size.get() {
+                    let width = win.default_width();
+                    (*settings.borrow_mut()).window.width = width;
+                }
             }
         ));
 
-        self.viewer
-            .webview()
-            .connect_context_menu(|_webview, context_menu, hit_test_result| {
+        self.viewer.webview().connect_context_menu(glib::clone!(
+            #[strong(rename_to = settings)]
+            self.settings,
+            #[strong(rename_to = state)]
+            self.state,
+            #[weak(rename_to = window)]
+            self.widget,
+            #[upgrade_or]
+            false,
+            move |webview, context_menu, hit_test_result| {
                 if hit_test_result.context_is_link() {
                     let uri = hit_test_result.link_uri().unwrap().to_string();
+                    let title = hit_test_result
+                        .link_label()
+                        .or_else(|| hit_test_result.link_title())
+                        .map(|title| title.to_string())
+                        .unwrap_or_default();
 
-                    let browsers = AppInfo::recommended_for_type("x-scheme-handler/http");
                     let open_link_menu = ContextMenu::new();
 
-                    for info in browsers.iter() {
-                        if info.id() == Some(GString::from("wv.desktop")) {
-                            // skip myself
-                            continue;
-                        };
-                        let action = gio::SimpleAction::new(&info.id().unwrap(), None);
-                        let name = info.name();
+                    // labels and desktop file ids aren't always valid action names
+                    for (i, target) in launcher::targets(&settings.borrow().reopen)
+                        .into_iter()
+                        .enumerate()
+                    {
+                        let action = gio::SimpleAction::new(&format!("open-link-{}", i), None);
+                        let name = target.label();
                         action.connect_activate(glib::clone!(
-                            #[strong]
-                            info,
+                            #[weak]
+                            webview,
                             #[strong]
                             uri,
+                            #[strong]
+                            title,
                             move |_action, _parameter| {
-                                let context =
-                                    gdk::Display::default().map(|d| d.app_launch_context());
-                                if let Err(e) = info.launch_uris(&[&uri], context.as_ref()) {
-                                    eprintln!("{:?}", e);
-                                }
+                                target.launch(&webview, &uri, &title);
                             }
                         ));
                         let item = webkit6::ContextMenuItem::from_gaction(&action, &name, None);
@@ -201,9 +334,75 @@ impl Window {
                         ContextMenuItem::with_submenu("Open Link with ...", &open_link_menu);
                     context_menu.insert(&open_link_item, 2);
                 }
+                if let (true, Some(uri), Some(action)) = (
+                    state.content_filters.is_loaded(),
+                    webview.uri(),
+                    window.lookup_action("toggle-content-blocking"),
+                ) {
+                    let label = if state.content_filters.is_disabled_for(&uri) {
+                        "Enable Content Blocking on This Site"
+                    } else {
+                        "Disable Content Blocking on This Site"
+                    };
+                    context_menu.append(&ContextMenuItem::new_separator());
+                    context_menu.append(&ContextMenuItem::from_gaction(&action, label, None));
+                }
                 false
-            });
+            }
+        ));
 
+        self.viewer.webview().connect_load_changed(glib::clone!(
+            #[strong(rename_to = state)]
+            self.state,
+            move |webview, event| {
+                if event == LoadEvent::Started {
+                    if let Some(uri) = webview.uri() {
+                        state.content_filters.apply(webview, &uri);
+                    }
+                }
+            }
+        ));
+        // the page zoomed last, to keep the level within a site
+        let committed_uri: Rc<RefCell<Option<String>>> = Rc::new(RefCell::new(None));
+        self.viewer.webview().connect_load_changed(glib::clone!(
+            #[strong(rename_to = state)]
+            self.state,
+            #[strong(rename_to = zoom_override)]
+            self.zoom_override,
+            #[strong]
+            committed_uri,
+            move |webview, event| {
+                if event == LoadEvent::Committed {
+                    let uri = webview.uri().map(|uri| uri.to_string()).unwrap_or_default();
+                    let level = zoom_override.get().unwrap_or_else(|| {
+                        zoom::level_for(
+                            committed_uri.borrow().as_deref(),
+                            &uri,
+                            webview.zoom_level(),
+                            state.zoom_levels.get(&uri),
+                            state.settings.borrow().zoom.default,
+                        )
+                    });
+                    webview.set_zoom_level(level);
+                    committed_uri.replace(Some(uri));
+                }
+            }
+        ));
+        self.viewer
+            .webview()
+            .connect_zoom_level_notify(glib::clone!(
+                #[weak(rename_to = zoom_button)]
+                self.zoom_button,
+                move |webview| {
+                    let level = webview.zoom_level();
+                    // stays shown so the menu is reachable at 100% too
+                    if (level - 1.0).abs() > 0.001 {
+                        zoom_button.set_label(&format!("{:.0}%", level * 100.0));
+                    } else {
+                        zoom_button.set_icon_name("zoom-original-symbolic");
+                    }
+                }
+            ));
         self.viewer.webview().connect_load_changed(glib::clone!(
             #[weak(rename_to = back_button)]
             self.back_button,
@@ -245,10 +444,40 @@ impl Window {
             }
         ));
 
+        if let Some(history) = &self.history {
+            self.viewer.webview().connect_load_changed(glib::clone!(
+                #[strong]
+                history,
+                move |webview, event| {
+                    if event == LoadEvent::Committed {
+                        if let Some(uri) = webview.uri().filter(|uri| history::is_recorded(uri)) {
+                            history.visit(&uri);
+                        }
+                    }
+                }
+            ));
+            self.viewer.webview().connect_title_notify(glib::clone!(
+                #[strong]
+                history,
+                move |webview| {
+                    if let (Some(uri), Some(title)) = (webview.uri(), webview.title()) {
+                        if !title.is_empty() {
+                            history.set_title(&uri, &title);
+                        }
+                    }
+                }
+            ));
+        }
+
         self.viewer.webview().connect_uri_notify(glib::clone!(
             #[weak(rename_to = favicontitle)]
             self.favicontitle,
+            #[weak(rename_to = star_button)]
+            self.star_button,
+            #[strong(rename_to = state)]
+            self.state,
             move |webview| {
+                bookmarkspopover::update_star(&star_button, &state, webview);
                 if let Some(uri) = webview.uri() {
                     favicontitle.set_subtitle(Some(uri.as_str()));
                 } else {
@@ -257,6 +486,96 @@ impl Window {
             }
         ));
 
+        // bookmarks may have been changed in another window
+        self.widget.connect_is_active_notify(glib::clone!(
+            #[weak(rename_to = star_button)]
+            self.star_button,
+            #[weak(rename_to = webview)]
+            self.viewer.webview(),
+            #[strong(rename_to = state)]
+            self.state,
+            move |win| {
+                if win.is_active() {
+                    bookmarkspopover::update_star(&star_button, &state, &webview);
+                }
+            }
+        ));
+
+        let entry = self.favicontitle.entry();
+        let completion = Rc::new(location::Completion::new(&entry));
+        completion.connect_selected(glib::clone!(
+            #[weak(rename_to = favicontitle)]
+            self.favicontitle,
+            #[weak(rename_to = webview)]
+            self.viewer.webview(),
+            move |uri| {
+                favicontitle.stop_editing();
+                webview.load_uri(uri);
+                webview.grab_focus();
+            }
+        ));
+        entry.connect_activate(glib::clone!(
+            #[weak(rename_to = favicontitle)]
+            self.favicontitle,
+            #[weak(rename_to = webview)]
+            self.viewer.webview(),
+            #[strong(rename_to = settings)]
+            self.settings,
+            #[strong]
+            completion,
+            move |entry| {
+                let uri = location::resolve(&entry.text(), &settings.borrow().search);
+                completion.hide();
+                favicontitle.stop_editing();
+                webview.load_uri(&uri);
+                webview.grab_focus();
+            }
+        ));
+        entry.connect_changed(glib::clone!(
+            #[weak(rename_to = favicontitle)]
+            self.favicontitle,
+            #[weak(rename_to = app)]
+            self.application,
+            #[strong(rename_to = history)]
+            self.history,
+            #[strong]
+            completion,
+            move |entry| {
+                if !favicontitle.is_editing() {
+                    return;
+                }
+                let text = entry.text();
+                if text.is_empty() {
+                    completion.hide();
+                } else {
+                    completion.update(location::suggestions(&app, history.as_deref(), &text));
+                }
+            }
+        ));
+        let entry_key_controller = gtk::EventControllerKey::new();
+        entry_key_controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+        entry_key_controller.connect_key_pressed(glib::clone!(
+            #[weak(rename_to = favicontitle)]
+            self.favicontitle,
+            #[weak(rename_to = webview)]
+            self.viewer.webview(),
+            #[strong]
+            completion,
+            #[upgrade_or]
+            glib::Propagation::Proceed,
+            move |_controller, key, _keycode, _state| match key {
+                gdk::Key::Escape => {
+                    completion.hide();
+                    favicontitle.stop_editing();
+                    webview.grab_focus();
+                    glib::Propagation::Stop
+                }
+                gdk::Key::Down if completion.focus_first() => glib::Propagation::Stop,
+                _ => glib::Propagation::Proceed,
+            }
+        ));
+        entry.add_controller(entry_key_controller);
+
         self.viewer.webview().connect_favicon_notify(glib::clone!(
             #[weak(rename_to = favicontitle)]
             self.favicontitle,
@@ -276,43 +595,61 @@ impl Window {
             .connect_download_started(glib::clone!(
                 #[weak(rename_to = window)]
                 self.widget,
-                move |_context, download| {
-                    download.connect_decide_destination(move |download, suggested_filename| {
-                        let suggested_filename = suggested_filename.to_owned();
-                        let window = window.clone();
-                        let download = download.clone();
-                        glib::MainContext::default().spawn_local(async move {
-                            let dialog = gtk::FileDialog::builder()
-                                .title("Download File")
-                                .initial_name(&suggested_filename)
-                                .build();
-                            if let Some(download_folder) =
-                                glib::user_special_dir(glib::UserDirectory::Downloads)
-                            {
-                                dialog.set_initial_folder(Some(&File::for_path(&download_folder)));
-                            }
-                            if let Ok(file) = dialog.save_future(Some(&window)).await {
-                                if let Some(path) = file.path() {
-                                    download.set_destination(&path.to_string_lossy());
-                                } else {
-                                    eprintln!("path is None for {}", file.uri());
-                                    download.cancel();
-                                }
-                            } else {
-                                download.cancel();
-                            }
-                        });
-
-                        true
-                    });
+                #[weak(rename_to = webview)]
+                self.viewer.webview(),
+                #[strong(rename_to = state)]
+                self.state,
+                move |_session, download| {
+                    // the session may be shared with other windows
+                    if download.web_view().as_ref() == Some(&webview) {
+                        state.downloads.add(download, window.upcast_ref());
+                    }
                 }
             ));
 
+        if let Some(manager) = self.viewer.webview().user_content_manager() {
+            hints::attach(
+                &manager,
+                glib::clone!(
+                    #[weak(rename_to = app)]
+                    self.application,
+                    #[weak(rename_to = webview)]
+                    self.viewer.webview(),
+                    #[strong(rename_to = state)]
+                    self.state,
+                    #[strong(rename_to = profile)]
+                    self.profile,
+                    move |hint: hints::Hint| match hint.target {
+                        HintTarget::Follow => (),
+                        HintTarget::Window => {
+                            let win =
+                                Window::new(&app, state.clone(), profile.clone(), Some(&webview));
+                            win.widget.present();
+                            win.load_uri(&hint.uri);
+                        }
+                        HintTarget::Browser => {
+                            // the same as "Open Link with ..." of the preferred browser
+                            let info = launcher::preferred_browser(&state.settings.borrow().reopen);
+                            if let Some(info) = info {
+                                launcher::Target::App(info).launch(
+                                    &webview,
+                                    &hint.uri,
+                                    &hint.title,
+                                );
+                            }
+                        }
+                    }
+                ),
+            );
+        }
+
         self.viewer.webview().connect_create(glib::clone!(
             #[weak(rename_to = app)]
             self.application,
-            #[strong(rename_to = settings)]
-            self.settings,
+            #[strong(rename_to = state)]
+            self.state,
+            #[strong(rename_to = profile)]
+            self.profile,
             #[upgrade_or]
             glib::object::Object::builder().build(),
             move |webview, navigation_action| {
@@ -322,7 +659,8 @@ impl Window {
                     if let Some(req) = navigation_action.request() {
                         if let Some(_uri) = req.uri() {
                             // action from "Open Link in New Window" context menu (maybe)
-                            let win = Window::new(&app, settings.clone(), Some(&webview));
+                            let win =
+                                Window::new(&app, state.clone(), profile.clone(), Some(&webview));
                             win.viewer.webview().connect_ready_to_show(glib::clone!(
                                 #[weak(rename_to = window)]
                                 win.widget,
@@ -340,8 +678,10 @@ impl Window {
         self.viewer.webview().connect_decide_policy(glib::clone!(
             #[weak(rename_to = app)]
             self.application,
-            #[strong(rename_to = settings)]
-            self.settings,
+            #[strong(rename_to = state)]
+            self.state,
+            #[strong(rename_to = profile)]
+            self.profile,
             #[upgrade_or]
             false,
             move |webview, decision, decision_type| {
@@ -356,18 +696,51 @@ impl Window {
                     decision.downcast_ref().unwrap();
                 let mut action = navigation_decision.navigation_action().unwrap();
 
+                // only what the user asked for is handed off; not loads of
+                // frames, reloads, history navigation or redirects. A window
+                // with nothing shown yet loads what was given to `wv`.
+                let is_requested = action.navigation_type() == NavigationType::LinkClicked
+                    || action.is_user_gesture()
+                    || webview
+                        .back_forward_list()
+                        .map_or(true, |list| list.current_item().is_none());
+                if let Some(uri) = action.request().and_then(|req| req.uri()) {
+                    match rules::decide(&state.settings.borrow().rules, &uri) {
+                        rules::Decision::Wv => (),
+                        rules::Decision::Block => {
+                            decision.ignore();
+                            return true;
+                        }
+                        rules::Decision::OpenWith(_) if !is_requested => (),
+                        rules::Decision::OpenWith(info) => {
+                            launcher::launch_app(&info, &uri);
+                            decision.ignore();
+                            if webview
+                                .back_forward_list()
+                                .map_or(true, |list| list.current_item().is_none())
+                            {
+                                // nothing to show in this window
+                                if let Some(window) = webview.root().and_downcast::<gtk::Window>() {
+                                    window.close();
+                                }
+                            }
+                            return true;
+                        }
+                    }
+                }
+
                 let button = action.mouse_button();
-                let state = action.modifiers();
+                let modifiers = action.modifiers();
                 if (action.is_user_gesture()
                     || action.navigation_type() == NavigationType::LinkClicked)
                     && (button == gdk::BUTTON_MIDDLE
-                        || state == gdk::ModifierType::SHIFT_MASK.bits()
-                        || state == gdk::ModifierType::CONTROL_MASK.bits())
+                        || modifiers == gdk::ModifierType::SHIFT_MASK.bits()
+                        || modifiers == gdk::ModifierType::CONTROL_MASK.bits())
                 {
                     let request = action.request().unwrap();
                     if let Some(uri) = request.uri() {
                         // open link in new window
-                        let win = Window::new(&app, settings.clone(), Some(&webview));
+                        let win = Window::new(&app, state.clone(), profile.clone(), Some(&webview));
                         win.widget.present();
                         win.load_uri(&uri);
                         decision.ignore();
@@ -490,6 +863,42 @@ impl Window {
         ));
     }
     fn setup_accels(&self) {
+        let new_window_action = SimpleAction::new("new-window", None);
+        new_window_action.connect_activate(glib::clone!(
+            #[weak(rename_to = app)]
+            self.application,
+            #[strong(rename_to = state)]
+            self.state,
+            #[strong(rename_to = profile)]
+            self.profile,
+            move |_action, _parameter| {
+                let mut profile = profile.clone();
+                profile.isolated = false;
+                let win = Window::new(&app, state.clone(), profile, None);
+                win.widget.present();
+                win.load_uri("about:blank");
+            }
+        ));
+        self.widget.add_action(&new_window_action);
+
+        let new_isolated_window_action = SimpleAction::new("new-isolated-window", None);
+        new_isolated_window_action.connect_activate(glib::clone!(
+            #[weak(rename_to = app)]
+            self.application,
+            #[strong(rename_to = state)]
+            self.state,
+            #[strong(rename_to = profile)]
+            self.profile,
+            move |_action, _parameter| {
+                let mut profile = profile.clone();
+                profile.isolated = true;
+                let win = Window::new(&app, state.clone(), profile, None);
+                win.widget.present();
+                win.load_uri("about:blank");
+            }
+        ));
+        self.widget.add_action(&new_isolated_window_action);
+
         let close_action = SimpleAction::new("close", None);
         close_action.connect_activate(glib::clone!(
             #[weak(rename_to = window)]
@@ -499,22 +908,26 @@ impl Window {
             }
         ));
         self.widget.add_action(&close_action);
-        self.application
-            .set_accels_for_action("win.close", &["<Primary>w"]);
 
         let find_action = SimpleAction::new("find", None);
         find_action.connect_activate(glib::clone!(
-            #[weak(rename_to = search_bar)]
-            self.viewer.search_bar(),
+            #[weak(rename_to = viewer)]
+            self.viewer,
             move |_action, _parameter| {
-                if !search_bar.is_search_mode() {
-                    search_bar.set_search_mode(true);
-                }
+                viewer.start_search(None);
             }
         ));
         self.widget.add_action(&find_action);
-        self.application
-            .set_accels_for_action("win.find", &["<Primary>f"]);
+
+        let find_selection_action = SimpleAction::new("find-selection", None);
+        find_selection_action.connect_activate(glib::clone!(
+            #[weak(rename_to = viewer)]
+            self.viewer,
+            move |_action, _parameter| {
+                viewer.search_selection();
+            }
+        ));
+        self.widget.add_action(&find_selection_action);
 
         let back_action = SimpleAction::new("back", None);
         back_action.connect_activate(glib::clone!(
@@ -525,8 +938,6 @@ impl Window {
             }
         ));
         self.widget.add_action(&back_action);
-        self.application
-            .set_accels_for_action("win.back", &["<alt>Left"]);
 
         let forward_action = SimpleAction::new("forward", None);
         forward_action.connect_activate(glib::clone!(
@@ -537,8 +948,6 @@ impl Window {
             }
         ));
         self.widget.add_action(&forward_action);
-        self.application
-            .set_accels_for_action("win.forward", &["<alt>Right"]);
 
         let reload_action = SimpleAction::new("reload", None);
         reload_action.connect_activate(glib::clone!(
@@ -549,24 +958,287 @@ impl Window {
             }
         ));
         self.widget.add_action(&reload_action);
-        self.application
-            .set_accels_for_action("win.reload", &["<Primary>r"]);
+
+        let reopen_preferred_action = SimpleAction::new("reopen-preferred", None);
+        reopen_preferred_action.connect_activate(glib::clone!(
+            #[strong(rename_to = settings)]
+            self.settings,
+            #[weak(rename_to = webview)]
+            self.viewer.webview(),
+            move |_action, _parameter| {
+                if let Some(uri) = webview.uri() {
+                    if let Some(info) = launcher::preferred_browser(&settings.borrow().reopen) {
+                        launcher::launch_app(&info, &uri);
+                    }
+                }
+            }
+        ));
+        self.widget.add_action(&reopen_preferred_action);
+
+        let history_action = SimpleAction::new("history", None);
+        history_action.set_enabled(self.history.is_some());
+        history_action.connect_activate(glib::clone!(
+            #[weak(rename_to = window)]
+            self.widget,
+            #[weak(rename_to = webview)]
+            self.viewer.webview(),
+            #[strong(rename_to = history)]
+            self.history,
+            #[strong(rename_to = profile)]
+            self.profile,
+            move |_action, _parameter| {
+                if let Some(history) = &history {
+                    historywindow::present(
+                        &window,
+                        &profile.name,
+                        history.clone(),
+                        glib::clone!(
+                            #[weak]
+                            webview,
+                            move |uri| {
+                                webview.load_uri(uri);
+                                webview.grab_focus();
+                            }
+                        ),
+                    );
+                }
+            }
+        ));
+        self.widget.add_action(&history_action);
+
+        let bookmark_action = SimpleAction::new("bookmark", None);
+        bookmark_action.connect_activate(glib::clone!(
+            #[weak(rename_to = star_button)]
+            self.star_button,
+            move |_action, _parameter| {
+                star_button.popup();
+            }
+        ));
+        self.widget.add_action(&bookmark_action);
+
+        let bookmarks_action = SimpleAction::new("bookmarks", None);
+        bookmarks_action.connect_activate(glib::clone!(
+            #[weak(rename_to = bookmarks_button)]
+            self.bookmarks_button,
+            move |_action, _parameter| {
+                bookmarks_button.popup();
+            }
+        ));
+        self.widget.add_action(&bookmarks_action);
+
+        let toggle_content_blocking_action = SimpleAction::new("toggle-content-blocking", None);
+        toggle_content_blocking_action.connect_activate(glib::clone!(
+            #[weak(rename_to = app)]
+            self.application,
+            #[strong(rename_to = state)]
+            self.state,
+            #[weak(rename_to = webview)]
+            self.viewer.webview(),
+            move |_action, _parameter| {
+                if let Some(uri) = webview.uri() {
+                    state.content_filters.toggle_site(&uri);
+                    state.content_filters.apply_all(&app);
+                    webview.reload();
+                }
+            }
+        ));
+        self.widget.add_action(&toggle_content_blocking_action);
+
+        for name in ["zoom-in", "zoom-out", "zoom-reset"] {
+            let zoom_action = SimpleAction::new(name, None);
+            zoom_action.connect_activate(glib::clone!(
+                #[weak(rename_to = webview)]
+                self.viewer.webview(),
+                #[strong(rename_to = state)]
+                self.state,
+                #[strong(rename_to = profile)]
+                self.profile,
+                #[strong(rename_to = zoom_override)]
+                self.zoom_override,
+                move |action, _parameter| {
+                    let level = webview.zoom_level();
+                    let default = state.settings.borrow().zoom.default;
+                    let level = match action.name().as_str() {
+                        "zoom-in" => zoom::step(level, true),
+                        "zoom-out" => zoom::step(level, false),
+                        _ => default,
+                    };
+                    zoom_override.set(None);
+                    webview.set_zoom_level(level);
+                    // nothing about sites visited should be left by private windows
+                    if profile.data_dir().is_some() {
+                        if let Some(uri) = webview.uri() {
+                            let level = Some(level).filter(|level| (level - default).abs() > 0.001);
+                            state.zoom_levels.set(&uri, level);
+                        }
+                    }
+                }
+            ));
+            self.widget.add_action(&zoom_action);
+        }
+
+        let zoom_text_only_action = SimpleAction::new_stateful(
+            "zoom-text-only",
+            None,
+            &self.settings.borrow().zoom.text_only.to_variant(),
+        );
+        zoom_text_only_action.connect_activate(glib::clone!(
+            #[weak(rename_to = app)]
+            self.application,
+            #[strong(rename_to = settings)]
+            self.settings,
+            move |_action, _parameter| {
+                let text_only = !settings.borrow().zoom.text_only;
+                settings.borrow_mut().zoom.text_only = text_only;
+                // the setting is global, so update all windows
+                for win in app.windows() {
+                    if let Some(viewer) = win.child().and_downcast::<viewer::Viewer>() {
+                        WebViewExt::settings(&viewer.webview())
+                            .unwrap()
+                            .set_zoom_text_only(text_only);
+                    }
+                    if let Some(action) = win
+                        .downcast_ref::<ApplicationWindow>()
+                        .and_then(|win| win.lookup_action("zoom-text-only"))
+                        .and_downcast::<SimpleAction>()
+                    {
+                        action.set_state(&text_only.to_variant());
+                    }
+                }
+            }
+        ));
+        self.widget.add_action(&zoom_text_only_action);
 
         let selecturl_action = SimpleAction::new("select-url", None);
         selecturl_action.connect_activate(glib::clone!(
             #[weak(rename_to = favicontitle)]
             self.favicontitle,
             move |_action, _parameter| {
-                favicontitle.select_subtitle();
+                favicontitle.start_editing();
             }
         ));
         self.widget.add_action(&selecturl_action);
-        self.application
-            .set_accels_for_action("win.select-url", &["<Primary>l"]);
+
+        for &(name, target) in &[
+            ("hints", HintTarget::Follow),
+            ("hints-new-window", HintTarget::Window),
+            ("hints-browser", HintTarget::Browser),
+        ] {
+            let hints_action = SimpleAction::new(name, None);
+            hints_action.connect_activate(glib::clone!(
+                #[weak(rename_to = webview)]
+                self.viewer.webview(),
+                move |_action, _parameter| {
+                    hints::start(&webview, target);
+                }
+            ));
+            self.widget.add_action(&hints_action);
+        }
+
+        let shortcuts_action = SimpleAction::new("shortcuts", None);
+        shortcuts_action.connect_activate(glib::clone!(
+            #[weak(rename_to = window)]
+            self.widget,
+            #[strong(rename_to = settings)]
+            self.settings,
+            move |_action, _parameter| {
+                keybindings::present_shortcuts(&window, &settings.borrow().keybindings);
+            }
+        ));
+        self.widget.add_action(&shortcuts_action);
+
+        let palette_action = SimpleAction::new("palette", None);
+        palette_action.connect_activate(glib::clone!(
+            #[weak(rename_to = window)]
+            self.widget,
+            #[weak(rename_to = app)]
+            self.application,
+            #[strong(rename_to = settings)]
+            self.settings,
+            #[weak(rename_to = webview)]
+            self.viewer.webview(),
+            move |_action, _parameter| {
+                palette::present(&window, &app, &settings, &webview);
+            }
+        ));
+        self.widget.add_action(&palette_action);
+    }
+    /// Apply command-line options, overriding settings for this window only.
+    pub fn apply_options(&self, options: &Options) {
+        if options.width.is_some() || options.height.is_some() {
+            self.save_size.set(false);
+            let (width, height) = self.widget.default_size();
+            self.widget.set_default_size(
+                options.width.unwrap_or(width),
+                options.height.unwrap_or(height),
+            );
+        }
+        if options.fullscreen {
+            self.widget.fullscreen();
+        }
+
+        let webview = self.viewer.webview();
+        let settings = WebViewExt::settings(&webview).unwrap();
+        if let Some(user_agent) = &options.user_agent {
+            settings.set_user_agent(Some(user_agent.as_str()));
+        }
+        if options.no_javascript {
+            settings.set_enable_javascript(false);
+        }
+        if let Some(zoom) = options.zoom {
+            self.zoom_override.set(Some(zoom));
+            webview.set_zoom_level(zoom);
+        }
     }
     pub fn load_uri(&self, uri: &str) {
         self.viewer.webview().load_uri(uri)
     }
+    pub fn load_uris(&self, uris: Vec<String>) {
+        self.viewer.load_uris(uris)
+    }
+    /// Restore back/forward list and go to its current item, or load `uri`
+    /// if there is no state to restore.
+    pub fn restore_session_state(&self, state: Option<&WebViewSessionState>, uri: &str) {
+        let webview = self.viewer.webview();
+        if let Some(state) = state {
+            webview.restore_session_state(state);
+            if let Some(item) = webview
+                .back_forward_list()
+                .and_then(|list| list.current_item())
+            {
+                webview.go_to_back_forward_list_item(&item);
+                return;
+            }
+        }
+        webview.load_uri(uri);
+    }
+    pub fn load_content(
+        &self,
+        content: &glib::Bytes,
+        content_type: ContentType,
+        base_uri: Option<&str>,
+    ) {
+        let webview = self.viewer.webview();
+        match content_type {
+            ContentType::Html => webview.load_bytes(content, Some("text/html"), None, base_uri),
+            ContentType::Markdown => {
+                let markdown = String::from_utf8_lossy(content);
+                let parser = pulldown_cmark::Parser::new_ext(
+                    &markdown,
+                    pulldown_cmark::Options::ENABLE_TABLES
+                        | pulldown_cmark::Options::ENABLE_STRIKETHROUGH
+                        | pulldown_cmark::Options::ENABLE_TASKLISTS
+                        | pulldown_cmark::Options::ENABLE_FOOTNOTES,
+                );
+                let mut html = String::new();
+                pulldown_cmark::html::push_html(&mut html, parser);
+                webview.load_html(&html, base_uri);
+            }
+            ContentType::Text => {
+                webview.load_bytes(content, Some("text/plain"), Some("UTF-8"), base_uri)
+            }
+        }
+    }
 }
 
 enum HistoryDirection {
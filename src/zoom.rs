@@ -0,0 +1,147 @@
+use serde_derive::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use crate::rules;
+use crate::settings;
+
+const ZOOM_FILE_NAME: &'static str = "zoom.toml";
+
+// levels Ctrl+Plus/Minus step through
+const STEPS: &[f64] = &[
+    0.3, 0.5, 0.67, 0.8, 0.9, 1.0, 1.1, 1.2, 1.33, 1.5, 1.7, 2.0, 2.4, 3.0,
+];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ZoomFile {
+    #[serde(default)]
+    hosts: BTreeMap<String, f64>,
+}
+
+/// Zoom levels remembered per host.
+pub struct ZoomLevels {
+    hosts: RefCell<BTreeMap<String, f64>>,
+}
+
+impl ZoomLevels {
+    pub fn load() -> Self {
+        let hosts = match settings::load_config_file(ZOOM_FILE_NAME) {
+            Some(data) => match toml::from_str::<ZoomFile>(&data) {
+                Ok(file) => file.hosts,
+                Err(e) => {
+                    dbg!(e);
+                    BTreeMap::new()
+                }
+            },
+            None => BTreeMap::new(),
+        };
+        Self {
+            hosts: RefCell::new(hosts),
+        }
+    }
+
+    /// Zoom level for the site of `uri`, if it has been changed.
+    pub fn get(&self, uri: &str) -> Option<f64> {
+        self.hosts.borrow().get(&rules::host(uri)?).copied()
+    }
+
+    /// Remember `level` for the site of `uri`, or forget it if None.
+    pub fn set(&self, uri: &str, level: Option<f64>) {
+        let host = match rules::host(uri) {
+            Some(host) => host,
+            None => return,
+        };
+        {
+            let mut hosts = self.hosts.borrow_mut();
+            let changed = match level {
+                Some(level) => hosts.insert(host, level) != Some(level),
+                None => hosts.remove(&host).is_some(),
+            };
+            if !changed {
+                return;
+            }
+        }
+        let file = ZoomFile {
+            hosts: self.hosts.borrow().clone(),
+        };
+        match toml::to_string(&file) {
+            Ok(data) => settings::save_config_file(ZOOM_FILE_NAME, &data),
+            Err(e) => {
+                dbg!(e);
+            }
+        }
+    }
+}
+
+/// The next level from `level`, larger if `zoom_in`.
+pub fn step(level: f64, zoom_in: bool) -> f64 {
+    // tolerate rounding errors of levels set elsewhere
+    let next = if zoom_in {
+        STEPS.iter().find(|step| **step > level + 0.01)
+    } else {
+        STEPS.iter().rev().find(|step| **step < level - 0.01)
+    };
+    next.copied().unwrap_or(level)
+}
+
+/// Zoom level for `uri` once committed in a view zoomed to `current` on
+/// `previous_uri`: kept within a host, which is all there is to remember
+/// in windows that don't store levels, otherwise `remembered` or `default`.
+pub fn level_for(
+    previous_uri: Option<&str>,
+    uri: &str,
+    current: f64,
+    remembered: Option<f64>,
+    default: f64,
+) -> f64 {
+    let host = rules::host(uri);
+    if host.is_some() && previous_uri.and_then(rules::host) == host {
+        current
+    } else {
+        remembered.unwrap_or(default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps() {
+        assert_eq!(step(1.0, true), 1.1);
+        assert_eq!(step(1.0, false), 0.9);
+        assert_eq!(step(1.05, true), 1.1);
+        assert_eq!(step(3.0, true), 3.0);
+    }
+
+    #[test]
+    fn same_host_keeps_level() {
+        let level = level_for(
+            Some("https://example.com/a"),
+            "https://Example.com/b?c",
+            1.5,
+            None,
+            1.0,
+        );
+        assert_eq!(level, 1.5);
+    }
+
+    #[test]
+    fn other_host_resets_level() {
+        let previous = Some("https://example.com/a");
+        assert_eq!(
+            level_for(previous, "https://example.org/", 1.5, None, 1.0),
+            1.0
+        );
+        assert_eq!(
+            level_for(previous, "https://example.org/", 1.5, Some(1.2), 1.0),
+            1.2
+        );
+        assert_eq!(level_for(None, "https://example.com/", 1.5, None, 1.0), 1.0);
+        // pages without a host aren't one site
+        assert_eq!(
+            level_for(Some("about:blank"), "about:blank", 1.5, None, 1.0),
+            1.0
+        );
+    }
+}